@@ -18,10 +18,6 @@ fn main() {
     let s = buf
         .replace("_multimap", "")
         .replace("Multimap", "")
-        .replace(
-            "table.remove_all(&k)?.is_empty()",
-            "table.remove(&k)?.is_none()",
-        )
         .replace("v.is_empty()", "v.is_none()")
         // ...
         ;
@@ -0,0 +1,257 @@
+//! Operations on several tables read from NDJSON, applied in one transaction
+
+use eyre::{Result, WrapErr, bail};
+use redb::{
+    MultimapTableDefinition, MultimapTableHandle, TableDefinition, TableHandle, WriteTransaction,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, BufWriter, Write},
+};
+
+use crate::{
+    DB, KVType, Schema,
+    changes::Changes,
+    cli::CliArgs,
+    parser, process, process_multimap,
+    transcode::{K, K_NAME, K_TREE, V, V_NAME, V_TREE, check_key, check_value, val_to_string},
+};
+
+/// A line of a batch, e.g. `{"op": "put", "table": "users", "key": 1, "value": "alice"}`
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+enum Op {
+    /// Insert a key-value pair, replacing the value of a normal table
+    Put {
+        table: String,
+        key: Value,
+        value: Value,
+        /// Types to open the table with instead of its resolved ones
+        schema: Option<String>,
+    },
+    /// Remove a key, or a single value of a multimap key
+    Remove {
+        table: String,
+        key: Value,
+        value: Option<Value>,
+        schema: Option<String>,
+    },
+    CreateTable {
+        table: String,
+        schema: String,
+        #[serde(default)]
+        multimap: bool,
+    },
+    DeleteTable {
+        table: String,
+    },
+}
+
+struct Batch<'a> {
+    args: CliArgs,
+    db: &'a DB,
+    w: &'a WriteTransaction,
+    /// Tables resolved or created so far
+    schemas: BTreeMap<String, Schema>,
+    /// Changes made by a dry run
+    changes: Option<Changes>,
+}
+
+/// Apply every operation of the input, committing only if all of them succeed, or report their
+/// changes and roll them back for a dry run
+pub fn run(args: &CliArgs, db: &DB, input: &str) -> Result<()> {
+    run_with_writer(args, db, input, Box::new(BufWriter::new(std::io::stdout())))
+}
+
+/// Same as [`run`], with the changes of a dry run written to `out`
+pub fn run_with_writer(args: &CliArgs, db: &DB, input: &str, out: Box<dyn Write>) -> Result<()> {
+    let input: Box<dyn BufRead> = match input {
+        "-" => Box::new(std::io::stdin().lock()),
+        path => Box::new(BufReader::new(
+            std::fs::File::open(path).wrap_err_with(|| format!("Can't open {path:?}"))?,
+        )),
+    };
+
+    let w = db.begin_write()?;
+    let mut batch = Batch {
+        // Types are resolved per table, and tables are only created by create-table
+        args: CliArgs {
+            schema: None,
+            create: false,
+            ..args.clone()
+        },
+        db,
+        w: &w,
+        schemas: BTreeMap::new(),
+        changes: args.dry_run.then(|| Changes::with_writer(args.format, out)),
+    };
+    let mut count = 0;
+    for (i, line) in input.lines().enumerate() {
+        let res = line.map_err(Into::into).and_then(|line| {
+            if line.trim().is_empty() {
+                return Ok(());
+            }
+            let op = serde_json::from_str(&line).wrap_err("Invalid operation")?;
+            count += 1;
+            batch.apply(op)
+        });
+        if let Err(err) = res {
+            drop(batch);
+            w.abort()?;
+            return Err(err.wrap_err(format!("Batch aborted at line {}", i + 1)));
+        }
+    }
+    let changes = batch.changes.take();
+    drop(batch);
+    crate::commit(w, args.dry_run)?;
+    match changes {
+        Some(changes) => {
+            let counts = changes.finish()?;
+            eprintln!("Dry run of {count} operations, nothing was written: {counts}");
+        }
+        None => eprintln!("Applied {count} operations"),
+    }
+
+    Ok(())
+}
+
+impl Batch<'_> {
+    fn apply(&mut self, op: Op) -> Result<()> {
+        match op {
+            Op::Put {
+                table,
+                key,
+                value,
+                schema,
+            } => {
+                let ty = self.open(&table, schema.as_deref())?;
+                let (k, v) = (check_key(key)?, check_value(value)?);
+                let changes = self.changes.as_mut();
+                if ty.is_multi {
+                    let def = MultimapTableDefinition::<K, V>::new(&table);
+                    let mut t = self.w.open_multimap_table(def)?;
+                    process_multimap::insert_multimap(&mut t, &k, &v, changes)?;
+                } else {
+                    let mut t = self.w.open_table(TableDefinition::<K, V>::new(&table))?;
+                    process::insert(&mut t, &k, &v, changes)?;
+                }
+            }
+            Op::Remove {
+                table,
+                key,
+                value,
+                schema,
+            } => {
+                let ty = self.open(&table, schema.as_deref())?;
+                let k = check_key(key)?;
+                let v = value.map(check_value).transpose()?;
+                let changes = self.changes.as_mut();
+                let found = if ty.is_multi {
+                    let def = MultimapTableDefinition::<K, V>::new(&table);
+                    let mut t = self.w.open_multimap_table(def)?;
+                    match &v {
+                        Some(v) => {
+                            let found = t.remove(&k, v)?;
+                            if let Some(changes) = changes.filter(|_| found) {
+                                changes.remove(&k, v)?;
+                            }
+                            found
+                        }
+                        None => process_multimap::remove_all_multimap(&mut t, &k, changes)?,
+                    }
+                } else if v.is_some() {
+                    bail!("Only values of multimap tables can be removed one by one");
+                } else {
+                    let mut t = self.w.open_table(TableDefinition::<K, V>::new(&table))?;
+                    process::remove_all(&mut t, &k, changes)?
+                };
+                if !found {
+                    bail!("No such key {:?} in {table:?}", val_to_string(ty.k_ty, k));
+                }
+            }
+            Op::CreateTable {
+                table,
+                schema,
+                multimap,
+            } => {
+                if self.kind(&table)?.is_some() {
+                    bail!("Table {table:?} already exists");
+                }
+                let schema = Schema::parse(&self.args, &table, &schema, multimap)?;
+                schema.enter();
+                if multimap {
+                    let def = MultimapTableDefinition::<K, V>::new(&table);
+                    self.w.open_multimap_table(def)?;
+                } else {
+                    self.w.open_table(TableDefinition::<K, V>::new(&table))?;
+                }
+                if let Some(changes) = &mut self.changes {
+                    changes.added_table(&table, schema.names())?;
+                }
+                self.schemas.insert(table, schema);
+            }
+            Op::DeleteTable { table } => {
+                match self.kind(&table)? {
+                    Some(true) => self
+                        .w
+                        .delete_multimap_table(MultimapTableDefinition::<K, V>::new(&table))?,
+                    Some(false) => self.w.delete_table(TableDefinition::<K, V>::new(&table))?,
+                    None => bail!("No such table {table:?}"),
+                };
+                if let Some(changes) = &mut self.changes {
+                    changes.removed_table(&table)?;
+                }
+                self.schemas.remove(&table);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a table exists in the transaction and is a multimap
+    fn kind(&self, table: &str) -> Result<Option<bool>> {
+        if self.w.list_tables()?.any(|t| t.name() == table) {
+            Ok(Some(false))
+        } else if self.w.list_multimap_tables()?.any(|t| t.name() == table) {
+            Ok(Some(true))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Enter the types of a table, resolved once or given with the operation
+    fn open(&mut self, table: &str, schema: Option<&str>) -> Result<KVType<&'static String>> {
+        let Some(is_multi) = self.kind(table)? else {
+            bail!("No such table {table:?}, create it with create-table");
+        };
+        let ty = match schema {
+            Some(schema) => Schema::parse(&self.args, table, schema, is_multi)?.enter(),
+            None => {
+                if !self.schemas.contains_key(table) {
+                    // Tables created in this batch are already known, so the others are committed
+                    let schema = Schema::resolve(&self.args, self.db, table)?;
+                    self.schemas.insert(table.to_string(), schema);
+                }
+                self.schemas[table].enter()
+            }
+        };
+        if let Some(changes) = &mut self.changes {
+            changes.begin_table(table, ty);
+        }
+
+        if !K_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Key type {:?} of table {table:?} can't be ordered",
+                K_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        if ty.is_multi && !V_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Value type {:?} of table {table:?} can't be ordered",
+                V_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        Ok(ty)
+    }
+}
@@ -0,0 +1,259 @@
+//! Entries inserted, changed and removed, reported as they happen by dry runs.
+//!
+//! JSON formats write JSON Patch-style operations, with paths like those of `-j`, e.g.
+//! `{"op": "replace", "path": "/users/5", "value": "bob", "old": "alice"}`. Values of multimap
+//! keys are added and removed one by one.
+
+use eyre::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fmt,
+    io::{BufWriter, Write},
+};
+
+use crate::{
+    KVType,
+    dump::{Header, Schema},
+    print::Format,
+    transcode::val_to_string,
+};
+
+#[derive(Serialize)]
+struct Patch<'a> {
+    op: &'static str,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old: Option<&'a Value>,
+}
+
+/// Types of a table, without its name
+#[derive(Serialize)]
+struct Types<'a> {
+    multimap: bool,
+    key: &'a Schema,
+    value: &'a Schema,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    /// Tables added, removed or retyped
+    pub tables: usize,
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Counts {
+            added,
+            changed,
+            removed,
+            tables,
+        } = self;
+        write!(
+            f,
+            "{added} added, {changed} changed and {removed} removed entries"
+        )?;
+        match tables {
+            0 => {}
+            1 => write!(f, ", and 1 table added, removed or retyped")?,
+            _ => write!(f, ", and {tables} tables added, removed or retyped")?,
+        }
+        Ok(())
+    }
+}
+
+pub struct Changes {
+    out: Box<dyn Write>,
+    format: Format,
+    table: String,
+    ty: Option<KVType<&'static String>>,
+    /// Whether the text header of the current table is written
+    header: bool,
+    /// JSON operations written
+    items: usize,
+    counts: Counts,
+}
+
+impl Changes {
+    pub fn new(format: Format) -> Self {
+        Self::with_writer(format, Box::new(BufWriter::new(std::io::stdout())))
+    }
+
+    pub fn with_writer(format: Format, out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            format,
+            table: String::new(),
+            ty: None,
+            header: false,
+            items: 0,
+            counts: Counts::default(),
+        }
+    }
+
+    /// Changes that follow are made to `table`
+    pub fn begin_table(&mut self, table: &str, ty: KVType<&'static String>) {
+        if self.table != table {
+            self.table.clear();
+            self.table.push_str(table);
+            self.header = false;
+        }
+        self.ty = Some(ty);
+    }
+
+    pub fn added_table(&mut self, table: &str, [k, v]: [&str; 2]) -> Result<()> {
+        self.counts.tables += 1;
+        match self.json() {
+            true => self.patch(
+                "add",
+                pointer(&[table]),
+                Some(&Value::Object(Default::default())),
+                None,
+            ),
+            false => Ok(writeln!(self.out, "+ table {table}: {k} -> {v}")?),
+        }
+    }
+
+    pub fn removed_table(&mut self, table: &str) -> Result<()> {
+        self.counts.tables += 1;
+        match self.json() {
+            true => self.patch("remove", pointer(&[table]), None, None),
+            false => Ok(writeln!(self.out, "- table {table}")?),
+        }
+    }
+
+    /// A table stored with other types, or turned into a multimap or back, written in JSON with
+    /// the only operation JSON Patch doesn't have
+    pub fn retyped_table(&mut self, table: &str, old: &Header, new: &Header) -> Result<()> {
+        self.counts.tables += 1;
+        if self.json() {
+            let types = |h: &Header| {
+                serde_json::to_value(Types {
+                    multimap: h.multimap,
+                    key: &h.key,
+                    value: &h.value,
+                })
+            };
+            let (old, new) = (types(old)?, types(new)?);
+            return self.patch("retype", pointer(&[table]), Some(&new), Some(&old));
+        }
+
+        // Types whose name didn't change have another class or width
+        let schema =
+            |s: &Schema, other: &Schema| match (s.name == other.name && s != other, s.width) {
+                (true, Some(width)) => format!("{} ({:?}, width {width})", s.name, s.class),
+                (true, None) => format!("{} ({:?}, variable width)", s.name, s.class),
+                (false, _) => s.name.clone(),
+            };
+        let types = |h: &Header, other: &Header| {
+            let multimap = if h.multimap { "multimap " } else { "" };
+            let (k, v) = (schema(&h.key, &other.key), schema(&h.value, &other.value));
+            format!("{multimap}{k} -> {v}")
+        };
+        let (old, new) = (types(old, new), types(new, old));
+        writeln!(self.out, "~ table {table}: {old} => {new}")?;
+        Ok(())
+    }
+
+    /// A new entry, or the new value of a key that had the value `old`
+    pub fn insert(&mut self, key: &Value, old: Option<Value>, new: &Value) -> Result<()> {
+        match old {
+            Some(old) if old == *new => Ok(()),
+            Some(old) => {
+                self.counts.changed += 1;
+                self.entry('~', "replace", key, Some(new), Some(&old))
+            }
+            None => {
+                self.counts.added += 1;
+                self.entry('+', "add", key, Some(new), None)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Value, old: &Value) -> Result<()> {
+        self.counts.removed += 1;
+        self.entry('-', "remove", key, None, Some(old))
+    }
+
+    pub fn finish(mut self) -> Result<Counts> {
+        match self.format {
+            Format::Json | Format::Pairs if self.items == 0 => writeln!(self.out, "[]")?,
+            Format::Json | Format::Pairs => writeln!(self.out, "\n]")?,
+            _ => {}
+        }
+        self.out.flush()?;
+        Ok(self.counts)
+    }
+
+    fn json(&self) -> bool {
+        matches!(self.format, Format::Json | Format::Pairs | Format::Ndjson)
+    }
+
+    fn entry(
+        &mut self,
+        sign: char,
+        op: &'static str,
+        key: &Value,
+        new: Option<&Value>,
+        old: Option<&Value>,
+    ) -> Result<()> {
+        let KVType { k_ty, v_ty, .. } = self.ty.unwrap();
+        let key = val_to_string(k_ty, key.clone());
+        if self.json() {
+            return self.patch(op, pointer(&[&self.table, &key]), new, old);
+        }
+
+        if !self.header {
+            writeln!(self.out, "{}:", self.table)?;
+            self.header = true;
+        }
+        let val = |v: &Value| val_to_string(v_ty, v.clone());
+        match (old, new) {
+            (Some(old), Some(new)) => {
+                writeln!(self.out, "{sign} {key}: {} -> {}", val(old), val(new))?
+            }
+            (Some(v), None) | (None, Some(v)) => writeln!(self.out, "{sign} {key}: {}", val(v))?,
+            (None, None) => writeln!(self.out, "{sign} {key}")?,
+        }
+        Ok(())
+    }
+
+    fn patch(
+        &mut self,
+        op: &'static str,
+        path: String,
+        value: Option<&Value>,
+        old: Option<&Value>,
+    ) -> Result<()> {
+        match (self.format, self.items) {
+            (Format::Ndjson, _) => {}
+            (_, 0) => write!(self.out, "[\n  ")?,
+            _ => write!(self.out, ",\n  ")?,
+        }
+        let patch = Patch {
+            op,
+            path,
+            value,
+            old,
+        };
+        serde_json::to_writer(&mut self.out, &patch)?;
+        if self.format == Format::Ndjson {
+            writeln!(self.out)?;
+        }
+        self.items += 1;
+        Ok(())
+    }
+}
+
+/// JSON Pointer to a table or an entry, see RFC 6901
+fn pointer(segments: &[&str]) -> String {
+    segments
+        .iter()
+        .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
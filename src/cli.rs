@@ -1,12 +1,24 @@
-use clap::Parser;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::{convert::Infallible, ffi::OsString, num::NonZeroUsize, path::Path, str::FromStr};
+
+use crate::print::Format;
 
 /// A CLI tool to read/modify redb database files
 #[derive(Parser, Debug, Clone)]
-#[command(version)]
-pub struct CliArgs {
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group = ArgGroup::new("selection").args(["key", "from", "to", "filter"]).multiple(true),
+    group = ArgGroup::new("write").args(["value", "remove", "batch"]).multiple(true)
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Database file
     #[arg(required = true)]
-    pub file: String,
+    pub file: Option<String>,
 
     /// Table name
     pub table: Option<String>,
@@ -21,43 +33,731 @@ pub struct CliArgs {
     #[arg(short, long, conflicts_with = "remove")]
     pub list: bool,
 
-    /// Create database file and table
-    #[arg(short, long)]
-    pub create: bool,
-
-    /// Remove key
-    #[arg(short, long, requires = "key")]
+    /// Remove key, or every entry in the range that passes the filter, from a single table
+    #[arg(short, long, requires = "selection", requires = "table")]
     pub remove: bool,
 
+    /// Print the entries a write would insert, change or remove, without writing them
+    #[arg(long, requires = "write")]
+    pub dry_run: bool,
+
+    /// Edit the value of a key as JSON in $EDITOR
+    #[arg(long, requires = "key", conflicts_with_all = ["value", "remove", "list", "delete"])]
+    pub edit: bool,
+
     /// Delete table
     #[arg(short, long, requires = "table", conflicts_with = "key")]
     pub delete: bool,
 
+    #[command(flatten)]
+    pub range: Range,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Show table stats
+    #[arg(long, conflicts_with = "list")]
+    pub stats: bool,
+
+    /// Check integrity
+    #[arg(long)]
+    pub check: bool,
+
+    /// Compact database
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Apply the operations of an NDJSON file, or stdin for -, in one transaction
+    #[arg(long, value_name = "FILE|-", conflicts_with = "table")]
+    pub batch: Option<String>,
+
+    #[command(flatten)]
+    pub options: Options,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print the value of a key, or entries of a table
+    Get {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+        /// Key (raw string or JSON value)
+        key: Option<String>,
+        #[command(flatten)]
+        range: Range,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Insert a key-value pair, replacing the value of a normal table
+    Put {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+        /// Key (raw string or JSON value)
+        key: String,
+        /// Value (raw string or JSON value)
+        value: String,
+        /// Print the entries that would be inserted or changed, without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove a key, a single value of a multimap key, or a range of entries
+    Rm {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+        /// Key (raw string or JSON value)
+        #[arg(required_unless_present_any = ["from", "to", "filter"])]
+        key: Option<String>,
+        /// Value (raw string or JSON value)
+        value: Option<String>,
+        #[command(flatten)]
+        range: Range,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Print the entries that would be removed, without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Edit the value of a key as JSON in $EDITOR, all values of a multimap key as an array
+    Edit {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+        /// Key (raw string or JSON value)
+        key: String,
+    },
+    /// List tables and types
+    Ls {
+        /// Database file
+        file: String,
+        /// Table name
+        table: Option<String>,
+    },
+    /// Print every table with its types and entries, in the format read by restore
+    Dump {
+        /// Database file
+        file: String,
+    },
+    /// Recreate tables from a dump, with the exact types of the original database
+    Restore {
+        /// Database file, created if it doesn't exist
+        file: String,
+        #[command(flatten)]
+        restore: RestoreArgs,
+    },
+    /// Insert entries read from NDJSON or CSV in one transaction
+    Load {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+        #[command(flatten)]
+        load: LoadArgs,
+        /// Print the entries that would be inserted or changed, without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Apply operations on several tables read from NDJSON, committing only if all succeed
+    Batch {
+        /// Database file
+        file: String,
+        /// Input file of {"op": .., "table": .., ..} lines, stdin if omitted or -
+        input: Option<String>,
+        /// Print the changes of the batch, without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compare the tables of two databases, or two tables, printing the entries that differ
+    Diff {
+        /// Old database file, optionally followed by a table, e.g. old.redb:users
+        left: Location,
+        /// New database file, optionally followed by a table
+        right: Location,
+    },
+    /// Copy a table, or every table, into another database with the same types
+    Copy {
+        #[command(flatten)]
+        copy: CopyArgs,
+    },
+    /// Delete a table
+    Drop {
+        /// Database file
+        file: String,
+        /// Table name
+        table: String,
+    },
+    /// Show table stats
+    Stats {
+        /// Database file
+        file: String,
+        /// Table name
+        table: Option<String>,
+    },
+    /// Check integrity
+    Check {
+        /// Database file
+        file: String,
+    },
+    /// Compact database
+    Compact {
+        /// Database file
+        file: String,
+    },
+    /// Create, list, restore and delete persistent savepoints
+    #[command(subcommand)]
+    Savepoint(SavepointCommand),
+    /// Start an interactive shell, keeping the database open between commands
+    Shell {
+        /// Database file
+        file: String,
+    },
+    /// Browse tables and entries in a full-screen terminal UI
+    Tui {
+        /// Database file
+        file: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SavepointCommand {
+    /// Save the current state of the database, printing the id of the savepoint
+    Create {
+        /// Database file
+        file: String,
+    },
+    /// List the ids of savepoints, oldest first
+    Ls {
+        /// Database file
+        file: String,
+    },
+    /// Roll the database back to a savepoint, deleting the savepoints created after it
+    Restore {
+        /// Database file
+        file: String,
+        /// Savepoint id
+        id: u64,
+    },
+    /// Delete a savepoint, so the space it holds on to can be reused
+    Rm {
+        /// Database file
+        file: String,
+        /// Savepoint id
+        id: u64,
+    },
+}
+
+/// A database file, optionally followed by a table, e.g. `app.redb:users`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub table: Option<String>,
+}
+
+impl FromStr for Location {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Files are split at the last ':' they exist before, so their names may contain ':'
+        let split = match Path::new(s).exists() {
+            true => None,
+            false => (s.rmatch_indices(':').map(|(i, _)| i))
+                .find(|&i| Path::new(&s[..i]).is_file())
+                .or_else(|| s.find(':')),
+        };
+        Ok(match split {
+            Some(i) => Location {
+                file: s[..i].to_string(),
+                table: Some(&s[i + 1..]).filter(|t| !t.is_empty()).map(Into::into),
+            },
+            None => Location {
+                file: s.to_string(),
+                table: None,
+            },
+        })
+    }
+}
+
+/// Operation on persistent savepoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Savepoint {
+    Create,
+    List,
+    Restore(u64),
+    Delete(u64),
+}
+
+/// Commands of the interactive shell, run on the table chosen with `use`
+#[derive(Parser, Debug, Clone)]
+#[command(multicall = true)]
+pub struct Shell {
+    #[command(subcommand)]
+    pub command: ShellCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ShellCommand {
+    /// Choose the table the other commands run on
+    Use {
+        /// Table name
+        table: String,
+    },
+    /// Print the value of a key
+    Get {
+        /// Key (raw string or JSON value)
+        key: String,
+    },
+    /// Insert a key-value pair, replacing the value of a normal table
+    Put {
+        /// Key (raw string or JSON value)
+        key: String,
+        /// Value (raw string or JSON value)
+        value: String,
+    },
+    /// Remove a key, or a single value of a multimap key
+    Rm {
+        /// Key (raw string or JSON value)
+        key: String,
+        /// Value (raw string or JSON value)
+        value: Option<String>,
+    },
+    /// Print entries in a key range, every entry by default
+    Range {
+        /// Lower key bound, inclusive (tuple and struct prefixes are allowed)
+        #[arg(long)]
+        from: Option<String>,
+        /// Upper key bound, exclusive (tuple and struct prefixes are allowed)
+        #[arg(long)]
+        to: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// List tables and types
+    Ls,
+    /// Start a transaction, so the following changes are committed together
+    Begin,
+    /// Commit the open transaction
+    Commit,
+    /// Discard the changes of the open transaction
+    Rollback,
+    /// Leave the shell, rolling back an open transaction
+    #[command(alias = "quit")]
+    Exit,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct Range {
+    /// Lower key bound, inclusive (tuple and struct prefixes are allowed)
+    #[arg(long, requires = "table", conflicts_with = "key")]
+    pub from: Option<String>,
+
+    /// Upper key bound, exclusive (tuple and struct prefixes are allowed)
+    #[arg(long, requires = "table", conflicts_with = "key")]
+    pub to: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct FilterArgs {
+    /// Print only entries for which a jq expression over {"key": .., "value": ..} holds
+    #[arg(long, value_name = "EXPR")]
+    pub filter: Option<String>,
+
+    /// Print only the given struct fields of values, e.g. name,address.city
+    #[arg(long, value_name = "FIELDS", value_delimiter = ',')]
+    pub fields: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct LoadArgs {
+    /// Input file of {"key": .., "value": ..} lines or CSV rows, stdin if omitted or -
+    pub input: Option<String>,
+
+    /// Input format, guessed from the file extension by default
+    #[arg(long, value_enum)]
+    pub input_format: Option<InputFormat>,
+
+    /// Map a CSV column to the key, value or one of their fields, e.g. id=key or
+    /// name=value.name (columns named like the target are mapped by default)
+    #[arg(long = "column", value_name = "COLUMN=TARGET")]
+    pub columns: Vec<String>,
+
+    /// Commit every N entries instead of once at the end
+    #[arg(long, value_name = "N")]
+    pub commit_every: Option<NonZeroUsize>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct RestoreArgs {
+    /// Input file written by dump, stdin if omitted or -
+    pub input: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CopyArgs {
+    /// Source database file, optionally followed by a table, e.g. app.redb:users
+    pub src: Location,
+
+    /// Destination database file, created if it doesn't exist, optionally followed by another
+    /// name for the table
+    pub dst: Location,
+
+    /// What to do with keys that already exist in the destination
+    #[arg(long, value_enum, default_value_t = Conflict::Fail)]
+    pub on_conflict: Conflict,
+}
+
+/// Handling of source keys found in the destination table, multimap keys with all their values
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Stop and write nothing
+    Fail,
+    /// Replace the value, or values of a multimap key
+    Overwrite,
+    /// Keep the destination entry
+    Skip,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+/// Options shared by all commands
+#[derive(Args, Debug, Clone, Default)]
+pub struct Options {
+    /// Create database file and table
+    #[arg(short, long, global = true)]
+    pub create: bool,
+
     /// Open as multimap
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub multimap: bool,
 
     /// Output JSON
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub json: bool,
 
+    /// Output JSON array of {"key": .., "value": ..} pairs
+    #[arg(short, long, global = true)]
+    pub pairs: bool,
+
+    /// Output newline-delimited JSON, one key-value pair per line
+    #[arg(long, global = true)]
+    pub ndjson: bool,
+
+    /// Output format: text, json, pairs, ndjson, csv, tsv or yaml
+    #[arg(long, value_enum, global = true, hide_possible_values = true)]
+    #[arg(conflicts_with_all = ["json", "pairs", "ndjson"])]
+    pub format: Option<Format>,
+
     /// Table schema, e.g. String -> String
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub schema: Option<String>,
 
+    /// Show and accept keys and values as hex-encoded bytes
+    #[arg(long, global = true)]
+    pub raw: bool,
+
     /// Open database read-only
-    #[arg(long, alias = "read-only")]
+    #[arg(long, alias = "read-only", global = true)]
     pub ro: bool,
+}
 
-    /// Show table stats
-    #[arg(long, conflicts_with = "list")]
+/// Operation to run, the same for a subcommand and its positional shorthand
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    pub file: String,
+    pub table: Option<String>,
+    pub key: Option<String>,
+    pub value: Option<String>,
+    pub list: bool,
+    pub create: bool,
+    pub remove: bool,
+    pub delete: bool,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub filter: Option<String>,
+    pub fields: Vec<String>,
+    /// Read entries to insert
+    pub load: Option<LoadArgs>,
+    /// Print tables in the format read by `restore`
+    pub dump: bool,
+    pub restore: Option<RestoreArgs>,
+    /// Apply the operations of a file in one transaction
+    pub batch: Option<String>,
+    /// Report the changes of a write, then roll them back
+    pub dry_run: bool,
+    /// Edit the value of the key in $EDITOR
+    pub edit: bool,
+    pub savepoint: Option<Savepoint>,
+    /// Compare the old and new database, or tables
+    pub diff: Option<[Location; 2]>,
+    pub copy: Option<CopyArgs>,
+    /// Read commands interactively
+    pub shell: bool,
+    pub tui: bool,
+    pub multimap: bool,
+    pub format: Format,
+    pub schema: Option<String>,
+    pub raw: bool,
+    pub ro: bool,
     pub stats: bool,
-
-    /// Check integrity
-    #[arg(long)]
     pub check: bool,
-
-    /// Compact database
-    #[arg(long)]
     pub compact: bool,
 }
+
+impl Cli {
+    /// Like [`Parser::parse`], but also accepts options before the subcommand, e.g. `-j get ..`
+    pub fn parse_args() -> Self {
+        Self::parse_from(hoist_subcommand(std::env::args_os().collect()))
+    }
+
+    pub fn into_args(self) -> CliArgs {
+        let Options {
+            create,
+            multimap,
+            json,
+            pairs,
+            ndjson,
+            format,
+            schema,
+            raw,
+            ro,
+        } = self.options;
+        let format = match () {
+            _ if ndjson => Format::Ndjson,
+            _ if pairs => Format::Pairs,
+            _ if json => Format::Json,
+            _ => format.unwrap_or_default(),
+        };
+        let args = CliArgs {
+            create,
+            multimap,
+            format,
+            schema,
+            raw,
+            ro,
+            ..Default::default()
+        };
+
+        let Some(command) = self.command else {
+            return CliArgs {
+                file: self.file.unwrap(),
+                table: self.table,
+                key: self.key,
+                value: self.value,
+                list: self.list,
+                remove: self.remove,
+                dry_run: self.dry_run,
+                edit: self.edit,
+                delete: self.delete,
+                from: self.range.from,
+                to: self.range.to,
+                filter: self.filter.filter,
+                fields: self.filter.fields,
+                stats: self.stats,
+                check: self.check,
+                compact: self.compact,
+                batch: self.batch,
+                ..args
+            };
+        };
+
+        match command {
+            Command::Get {
+                file,
+                table,
+                key,
+                range,
+                filter,
+            } => CliArgs {
+                file,
+                table: Some(table),
+                key,
+                from: range.from,
+                to: range.to,
+                filter: filter.filter,
+                fields: filter.fields,
+                ..args
+            },
+            Command::Put {
+                file,
+                table,
+                key,
+                value,
+                dry_run,
+            } => CliArgs {
+                file,
+                table: Some(table),
+                key: Some(key),
+                value: Some(value),
+                dry_run,
+                ..args
+            },
+            Command::Rm {
+                file,
+                table,
+                key,
+                value,
+                range,
+                filter,
+                dry_run,
+            } => CliArgs {
+                file,
+                table: Some(table),
+                key,
+                value,
+                from: range.from,
+                to: range.to,
+                filter: filter.filter,
+                fields: filter.fields,
+                remove: true,
+                dry_run,
+                ..args
+            },
+            Command::Edit { file, table, key } => CliArgs {
+                file,
+                table: Some(table),
+                key: Some(key),
+                edit: true,
+                ..args
+            },
+            Command::Ls { file, table } => CliArgs {
+                file,
+                table,
+                list: true,
+                ..args
+            },
+            Command::Dump { file } => CliArgs {
+                file,
+                dump: true,
+                ..args
+            },
+            Command::Restore { file, restore } => CliArgs {
+                file,
+                create: true,
+                restore: Some(restore),
+                ..args
+            },
+            Command::Load {
+                file,
+                table,
+                load,
+                dry_run,
+            } => CliArgs {
+                file,
+                table: Some(table),
+                load: Some(load),
+                dry_run,
+                ..args
+            },
+            Command::Batch {
+                file,
+                input,
+                dry_run,
+            } => CliArgs {
+                file,
+                batch: Some(input.unwrap_or_else(|| "-".into())),
+                dry_run,
+                ..args
+            },
+            Command::Diff { left, right } => CliArgs {
+                file: left.file.clone(),
+                diff: Some([left, right]),
+                ..args
+            },
+            Command::Copy { copy } => CliArgs {
+                file: copy.src.file.clone(),
+                copy: Some(copy),
+                ..args
+            },
+            Command::Drop { file, table } => CliArgs {
+                file,
+                table: Some(table),
+                delete: true,
+                ..args
+            },
+            Command::Stats { file, table } => CliArgs {
+                file,
+                table,
+                stats: true,
+                ..args
+            },
+            Command::Check { file } => CliArgs {
+                file,
+                check: true,
+                ..args
+            },
+            Command::Compact { file } => CliArgs {
+                file,
+                compact: true,
+                ..args
+            },
+            Command::Savepoint(command) => {
+                let (file, savepoint) = match command {
+                    SavepointCommand::Create { file } => (file, Savepoint::Create),
+                    SavepointCommand::Ls { file } => (file, Savepoint::List),
+                    SavepointCommand::Restore { file, id } => (file, Savepoint::Restore(id)),
+                    SavepointCommand::Rm { file, id } => (file, Savepoint::Delete(id)),
+                };
+                CliArgs {
+                    file,
+                    savepoint: Some(savepoint),
+                    ..args
+                }
+            }
+            Command::Shell { file } => CliArgs {
+                file,
+                shell: true,
+                ..args
+            },
+            Command::Tui { file } => CliArgs {
+                file,
+                tui: true,
+                ..args
+            },
+        }
+    }
+}
+
+/// Move the subcommand in front of the options preceding it, since any argument before it makes
+/// clap take its name for the database file of the positional form
+pub(crate) fn hoist_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let cmd = Cli::command();
+    let takes_value = |arg: Option<&clap::Arg>| arg.is_some_and(|a| a.get_action().takes_values());
+
+    let mut i = 1;
+    while let Some(s) = args.get(i).and_then(|s| s.to_str()) {
+        if s == "--" {
+            break;
+        } else if let Some(long) = s.strip_prefix("--") {
+            let arg = cmd.get_arguments().find(|a| a.get_long() == Some(long));
+            if takes_value(arg) {
+                i += 1;
+            }
+        } else if let Some(short) = s.strip_prefix('-')
+            && let Some(c) = short.chars().last()
+        {
+            let arg = cmd.get_arguments().find(|a| a.get_short() == Some(c));
+            if takes_value(arg) && short.len() == c.len_utf8() {
+                i += 1;
+            }
+        } else {
+            if cmd.find_subcommand(s).is_some() {
+                let command = args.remove(i);
+                args.insert(1, command);
+            }
+            break;
+        }
+        i += 1;
+    }
+
+    args
+}
@@ -0,0 +1,148 @@
+use std::cmp::Ordering;
+
+use crate::parser::{TypeDesc, take_n, take_varint};
+
+impl TypeDesc {
+    /// Ordering of encoded values.
+    ///
+    /// Walks both buffers directly, following the byte layout of redb's built-in [`redb::Key`]
+    /// implementations, so comparisons don't allocate. The type must satisfy
+    /// [`can_order`](crate::parser::can_order).
+    pub fn compare(&self, l: &[u8], r: &[u8]) -> Ordering {
+        match self {
+            Self::Bool => Ord::cmp(&l[0], &r[0]),
+            Self::Char => compare_int(<char as redb::Value>::fixed_width().unwrap(), false, l, r),
+            Self::Int { bits, signed } => compare_int(bits / 8, *signed, l, r),
+            // Same as `total_cmp`, so NaNs and signed zeros have a fixed place
+            Self::Float { bits: 32 } => f32::total_cmp(
+                &f32::from_le_bytes(l[..4].try_into().unwrap()),
+                &f32::from_le_bytes(r[..4].try_into().unwrap()),
+            ),
+            Self::Float { .. } => f64::total_cmp(
+                &f64::from_le_bytes(l[..8].try_into().unwrap()),
+                &f64::from_le_bytes(r[..8].try_into().unwrap()),
+            ),
+            // UTF-8 preserves codepoint order
+            Self::String | Self::Slice => Ord::cmp(l, r),
+            Self::Option { ty, .. } => match (l[0], r[0]) {
+                (0, 0) => Ordering::Equal,
+                (0, _) => Ordering::Less,
+                (_, 0) => Ordering::Greater,
+                _ => ty.compare(&l[1..], &r[1..]),
+            },
+            Self::Array { ty, n, .. } => {
+                if let Some(width) = ty.width() {
+                    let (l, r) = (l.chunks_exact(width), r.chunks_exact(width));
+                    for (l, r) in Iterator::zip(l, r).take(*n) {
+                        let c = ty.compare(l, r);
+                        if !c.is_eq() {
+                            return c;
+                        }
+                    }
+                    return Ordering::Equal;
+                }
+                // Elements are preceded by a table of their end offsets
+                let end = |data: &[u8], i: usize| {
+                    u32::from_le_bytes(data[i * 4..(i + 1) * 4].try_into().unwrap()) as usize
+                };
+                let (mut l_start, mut r_start) = (n * 4, n * 4);
+                for i in 0..*n {
+                    let (l_end, r_end) = (end(l, i), end(r, i));
+                    let c = ty.compare(&l[l_start..l_end], &r[r_start..r_end]);
+                    if !c.is_eq() {
+                        return c;
+                    }
+                    (l_start, r_start) = (l_end, r_end);
+                }
+                Ordering::Equal
+            }
+            Self::Vec(ty) => {
+                let width = ty.width();
+                let (mut l, mut r) = (l, r);
+                let l_n = take_varint(&mut l).unwrap();
+                let r_n = take_varint(&mut r).unwrap();
+                for _ in 0..Ord::min(l_n, r_n) {
+                    let l_len = width.unwrap_or_else(|| take_varint(&mut l).unwrap());
+                    let r_len = width.unwrap_or_else(|| take_varint(&mut r).unwrap());
+                    let c = ty.compare(
+                        take_n(&mut l, l_len).unwrap(),
+                        take_n(&mut r, r_len).unwrap(),
+                    );
+                    if !c.is_eq() {
+                        return c;
+                    }
+                }
+                Ord::cmp(&l_n, &r_n)
+            }
+            Self::Tuple { fields, .. } | Self::TupleStruct { fields, .. } => {
+                match fields.as_slice() {
+                    [] => Ordering::Equal,
+                    // Single element tuples and newtype structs are encoded as the element itself
+                    [ty] => ty.compare(l, r),
+                    _ => compare_fields(fields.iter(), l, r),
+                }
+            }
+            // Same as a derived `Ord`, by fields in declaration order
+            Self::Struct { fields, .. } => compare_fields(fields.iter().map(|(_, ty)| ty), l, r),
+            // Unknown types are only iterated, never looked up
+            Self::Raw { ty, .. } => match ty {
+                Some(ty) => ty.compare(l, r),
+                None => Ord::cmp(l, r),
+            },
+            // Same as a derived `Ord`, by variant index, then by fields
+            Self::Enum { variants, .. } => {
+                Ord::cmp(&l[0], &r[0]).then_with(|| match variants.get(l[0] as usize) {
+                    Some((_, Some(ty))) => ty.compare(&l[1..], &r[1..]),
+                    Some((_, None)) => Ordering::Equal,
+                    // Variants missing from the type name are only ordered by their bytes
+                    None => Ord::cmp(l, r),
+                })
+            }
+        }
+    }
+}
+
+fn compare_int(width: usize, signed: bool, l: &[u8], r: &[u8]) -> Ordering {
+    let (l, r) = (&l[..width], &r[..width]);
+    // Most significant byte holds the sign
+    let sign = if signed { 0x80 } else { 0 };
+    Ord::cmp(&(l[width - 1] ^ sign), &(r[width - 1] ^ sign))
+        .then_with(|| Iterator::cmp(l.iter().rev(), r.iter().rev()))
+}
+
+fn compare_fields<'a>(
+    fields: impl ExactSizeIterator<Item = &'a TypeDesc> + Clone,
+    l: &[u8],
+    r: &[u8],
+) -> Ordering {
+    // Lengths of variable width fields, except the last one, precede the data
+    let Some(last) = fields.len().checked_sub(1) else {
+        return Ordering::Equal;
+    };
+    let (mut l_lens, mut r_lens) = (l, r);
+    let (mut l_data, mut r_data) = (l, r);
+    for ty in fields.clone().take(last) {
+        if ty.width().is_none() {
+            take_varint(&mut l_data).unwrap();
+            take_varint(&mut r_data).unwrap();
+        }
+    }
+
+    for (i, ty) in fields.enumerate() {
+        let len = |lens: &mut &[u8], data: &[u8]| match ty.width() {
+            Some(width) => width,
+            None if i == last => data.len(),
+            None => take_varint(lens).unwrap(),
+        };
+        let l_len = len(&mut l_lens, l_data);
+        let r_len = len(&mut r_lens, r_data);
+        let c = ty.compare(
+            take_n(&mut l_data, l_len).unwrap(),
+            take_n(&mut r_data, r_len).unwrap(),
+        );
+        if !c.is_eq() {
+            return c;
+        }
+    }
+    Ordering::Equal
+}
@@ -0,0 +1,136 @@
+//! Tables copied between databases, with the type names and widths of the source
+
+use eyre::{Result, WrapErr, bail};
+use redb::{
+    Database, MultimapTableDefinition, MultimapTableHandle, ReadOnlyDatabase,
+    ReadableMultimapTable, ReadableTable, TableDefinition, TableHandle,
+};
+use serde_json::Value;
+
+use crate::{
+    DB, Schema,
+    cli::{CliArgs, Conflict, CopyArgs},
+    parser,
+    transcode::{K, K_NAME, K_TREE, V, V_NAME, V_TREE, val_to_string},
+};
+
+/// Stream the source tables into the destination in one transaction, committed once all of
+/// them are copied
+pub fn run(args: &CliArgs, copy: &CopyArgs) -> Result<()> {
+    let CopyArgs {
+        src,
+        dst,
+        on_conflict,
+    } = copy;
+
+    // A file can't be opened twice, but it can be read while it's written
+    let same_file = match (
+        std::fs::canonicalize(&src.file),
+        std::fs::canonicalize(&dst.file),
+    ) {
+        (Ok(src), Ok(dst)) => src == dst,
+        _ => false,
+    };
+    let src_db = match same_file {
+        true => None,
+        false => Some(DB::R(
+            ReadOnlyDatabase::open(&src.file)
+                .wrap_err_with(|| format!("Can't open {:?}", src.file))?,
+        )),
+    };
+    let dst_db =
+        DB::RW(Database::create(&dst.file).wrap_err_with(|| format!("Can't open {:?}", dst.file))?);
+    let src_db = src_db.as_ref().unwrap_or(&dst_db);
+
+    let pairs = match (&src.table, &dst.table) {
+        (Some(from), to) => vec![(from.clone(), to.clone().unwrap_or_else(|| from.clone()))],
+        (None, Some(to)) => bail!("Name the source table to copy into {to:?}"),
+        (None, None) => {
+            let r = src_db.begin_read()?;
+            let tables = r.list_tables()?.map(|t| t.name().to_string());
+            let multimaps = r.list_multimap_tables()?.map(|t| t.name().to_string());
+            tables.chain(multimaps).map(|t| (t.clone(), t)).collect()
+        }
+    };
+
+    let args = CliArgs {
+        multimap: false,
+        create: false,
+        ..args.clone()
+    };
+    let w = dst_db.begin_write()?;
+    for (from, to) in pairs {
+        if same_file && from == to {
+            bail!("Can't copy {from:?} onto itself");
+        }
+        let ty = Schema::resolve(&args, src_db, &from)?.enter();
+        if !K_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Key type {:?} of table {from:?} can't be ordered",
+                K_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        if ty.is_multi && !V_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Value type {:?} of table {from:?} can't be ordered",
+                V_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        let mismatch = || format!("Can't open {to:?} with the types of {from:?}");
+        let exists = |k: &Value| match on_conflict {
+            Conflict::Fail => bail!(
+                "Key {:?} already exists in {to:?}",
+                val_to_string(ty.k_ty, k.clone())
+            ),
+            _ => Ok(*on_conflict),
+        };
+
+        let r = src_db.begin_read()?;
+        let (mut copied, mut skipped) = (0, 0);
+        if ty.is_multi {
+            let src_table = r.open_multimap_table(MultimapTableDefinition::<K, V>::new(&from))?;
+            let def = MultimapTableDefinition::<K, V>::new(&to);
+            let mut dst_table = w.open_multimap_table(def).wrap_err_with(mismatch)?;
+            for entry in src_table.range::<Value>(..)? {
+                let (k, values) = entry?;
+                let k = k.value();
+                if !dst_table.get(&k)?.is_empty() {
+                    match exists(&k)? {
+                        Conflict::Skip => {
+                            skipped += 1;
+                            continue;
+                        }
+                        _ => drop(dst_table.remove_all(&k)?),
+                    }
+                }
+                for v in values {
+                    dst_table.insert(&k, v?.value())?;
+                    copied += 1;
+                }
+            }
+        } else {
+            let src_table = r.open_table(TableDefinition::<K, V>::new(&from))?;
+            let def = TableDefinition::<K, V>::new(&to);
+            let mut dst_table = w.open_table(def).wrap_err_with(mismatch)?;
+            for entry in src_table.range::<Value>(..)? {
+                let (k, v) = entry?;
+                let k = k.value();
+                if dst_table.get(&k)?.is_some() && exists(&k)? == Conflict::Skip {
+                    skipped += 1;
+                    continue;
+                }
+                dst_table.insert(&k, v.value())?;
+                copied += 1;
+            }
+        }
+        match skipped {
+            0 => eprintln!("Copied {copied} entries from {from:?} into {to:?}"),
+            _ => eprintln!(
+                "Copied {copied} entries from {from:?} into {to:?}, skipped {skipped} existing keys"
+            ),
+        }
+    }
+    w.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Bulk removal of the entries in a key range that pass a filter
+
+use eyre::{Result, WrapErr};
+use redb::{MultimapTable, ReadableMultimapTable, Table};
+use serde_json::Value;
+use std::ops::Bound;
+
+use crate::{
+    changes::Changes,
+    filter::Filter,
+    transcode::{K, V, val_to_string},
+};
+
+pub type Range = (Bound<Value>, Bound<Value>);
+
+/// Remove matching entries and return their count, removed entries are reported to a dry run
+pub fn remove(
+    table: &mut Table<K, V>,
+    range: Range,
+    filter: Option<&Filter>,
+    k_ty: &'static str,
+    mut changes: Option<&mut Changes>,
+) -> Result<usize> {
+    // The predicate can't fail, so the first error is kept until the end
+    let mut err = None;
+    let removed = table.extract_from_if::<Value, _>(range, |mut k, mut v| {
+        let Some(filter) = filter else { return true };
+        match matches(filter, k_ty, &mut k, &mut v) {
+            Ok(matches) => matches,
+            Err(e) => {
+                err.get_or_insert(e);
+                false
+            }
+        }
+    })?;
+
+    let mut count = 0;
+    for entry in removed {
+        let (k, v) = entry?;
+        if let Some(changes) = changes.as_deref_mut() {
+            changes.remove(&k.value(), &v.value())?;
+        }
+        count += 1;
+    }
+
+    match err {
+        Some(err) => Err(err),
+        None => Ok(count),
+    }
+}
+
+/// Like [`remove`], entries are collected first, since multimaps can't remove while iterating
+pub fn remove_multimap(
+    table: &mut MultimapTable<K, V>,
+    range: Range,
+    filter: Option<&Filter>,
+    k_ty: &'static str,
+    mut changes: Option<&mut Changes>,
+) -> Result<usize> {
+    // Values to remove of each key, or none to remove all of them
+    let mut keys = Vec::new();
+    for entry in table.range::<Value>(range)? {
+        let (k, values) = entry?;
+        let mut k = k.value();
+        let Some(filter) = filter else {
+            keys.push((k, None));
+            continue;
+        };
+        let mut matched = Vec::new();
+        for v in values {
+            let mut v = v?.value();
+            if matches(filter, k_ty, &mut k, &mut v)? {
+                matched.push(v);
+            }
+        }
+        if !matched.is_empty() {
+            keys.push((k, Some(matched)));
+        }
+    }
+
+    let mut count = 0;
+    for (k, values) in keys {
+        let mut removed = |v: Value| -> Result<()> {
+            if let Some(changes) = changes.as_deref_mut() {
+                changes.remove(&k, &v)?;
+            }
+            count += 1;
+            Ok(())
+        };
+        match values {
+            None => {
+                for v in table.remove_all(&k)? {
+                    removed(v?.value())?;
+                }
+            }
+            Some(values) => {
+                for v in values {
+                    table.remove(&k, &v)?;
+                    removed(v)?;
+                }
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+fn matches(filter: &Filter, k_ty: &'static str, k: &mut Value, v: &mut Value) -> Result<bool> {
+    filter
+        .matches(k, v)
+        .wrap_err_with(|| format!("Can't filter key {}", val_to_string(k_ty, k.clone())))
+}
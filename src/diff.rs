@@ -0,0 +1,197 @@
+//! Differences between the tables of two databases, reported like the changes of a dry run
+
+use eyre::{Result, WrapErr, bail};
+use redb::{
+    MultimapTableDefinition, MultimapTableHandle, ReadOnlyDatabase, TableDefinition, TableHandle,
+};
+use serde_json::Value;
+use std::{cmp::Ordering, collections::BTreeSet};
+
+use crate::{
+    DB, Schema, WARNING,
+    changes::Changes,
+    cli::{CliArgs, Location},
+    dump::Header,
+    parser,
+    transcode::{K, K_TREE, V},
+};
+
+/// Entries of a table in key order, each with its values, so one for normal tables
+type Entries = Box<dyn Iterator<Item = Result<(Value, Vec<Value>)>>>;
+
+/// Compare the tables of `left` and `right`, printing what differs in the new one
+pub fn run(args: &CliArgs, left: &Location, right: &Location) -> Result<()> {
+    let open = |file: &str| -> Result<DB> {
+        let db = ReadOnlyDatabase::open(file).wrap_err_with(|| format!("Can't open {file:?}"))?;
+        Ok(DB::R(db))
+    };
+    let (l_db, r_db) = (open(&left.file)?, open(&right.file)?);
+    let mut changes = Changes::new(args.format);
+    diff(
+        args,
+        [
+            (&l_db, left.table.as_deref()),
+            (&r_db, right.table.as_deref()),
+        ],
+        &mut changes,
+    )?;
+    let counts = changes.finish()?;
+    eprintln!("{counts}");
+    Ok(())
+}
+
+/// Report the differences of the old and new database, or of the tables given with them
+pub fn diff(
+    args: &CliArgs,
+    [(l_db, left), (r_db, right)]: [(&DB, Option<&str>); 2],
+    changes: &mut Changes,
+) -> Result<()> {
+    let (l_tables, r_tables) = (tables(l_db)?, tables(r_db)?);
+
+    let pairs = match (left, right) {
+        (Some(l), Some(r)) => vec![(Some(l), Some(r))],
+        (Some(t), None) | (None, Some(t)) => vec![(Some(t), Some(t))],
+        (None, None) => {
+            let names: BTreeSet<_> = l_tables.union(&r_tables).collect();
+            (names.into_iter())
+                .map(|t| {
+                    let t = t.as_str();
+                    (
+                        l_tables.contains(t).then_some(t),
+                        r_tables.contains(t).then_some(t),
+                    )
+                })
+                .collect()
+        }
+    };
+
+    let args = CliArgs {
+        multimap: false,
+        create: false,
+        ..args.clone()
+    };
+    for pair in pairs {
+        // Entries are reported under the name of the old table
+        let (l, r) = match pair {
+            (Some(l), Some(r)) => (l, r),
+            (Some(l), None) => {
+                changes.removed_table(l)?;
+                continue;
+            }
+            (None, Some(r)) => {
+                let schema = Schema::resolve(&args, r_db, r)?;
+                changes.added_table(r, schema.names())?;
+                continue;
+            }
+            (None, None) => unreachable!(),
+        };
+        for (side, tables, table) in [("old", &l_tables, l), ("new", &r_tables, r)] {
+            if !tables.contains(table) {
+                bail!("No such table {table:?} in the {side} database");
+            }
+        }
+
+        let l_schema = Schema::resolve(&args, l_db, l)?;
+        let r_schema = Schema::resolve(&args, r_db, r)?;
+        let l_header = Header::current(l, l_schema.enter().is_multi);
+        let ty = r_schema.enter();
+        let r_header = Header::current(l, ty.is_multi);
+        if l_header != r_header {
+            changes.retyped_table(l, &l_header, &r_header)?;
+        }
+        if l_header.multimap != r_header.multimap || l_header.key != r_header.key {
+            continue;
+        }
+        if !r_schema.can_order() {
+            eprintln!("{WARNING} Key type of {l:?} can't be ordered, its entries aren't compared");
+            continue;
+        }
+        changes.begin_table(l, ty);
+
+        // Values are decoded with the types of their side, keys are of the same type
+        let reenter = l_header.value != r_header.value;
+        let next = |entries: &mut Entries, schema: &Schema| {
+            if reenter {
+                schema.enter();
+            }
+            entries.next().transpose()
+        };
+        l_schema.enter();
+        let mut l_entries = entries(l_db, l)?;
+        r_schema.enter();
+        let mut r_entries = entries(r_db, r)?;
+        let mut l_entry = next(&mut l_entries, &l_schema)?;
+        let mut r_entry = next(&mut r_entries, &r_schema)?;
+        loop {
+            let order = match (&l_entry, &r_entry) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((l_key, _)), Some((r_key, _))) => {
+                    K_TREE.with_borrow(|t| parser::ordering(t.as_ref().unwrap(), l_key, r_key))?
+                }
+            };
+            match order {
+                Ordering::Less => {
+                    let (key, values) = l_entry.take().unwrap();
+                    for value in &values {
+                        changes.remove(&key, value)?;
+                    }
+                    l_entry = next(&mut l_entries, &l_schema)?;
+                }
+                Ordering::Greater => {
+                    let (key, values) = r_entry.take().unwrap();
+                    for value in &values {
+                        changes.insert(&key, None, value)?;
+                    }
+                    r_entry = next(&mut r_entries, &r_schema)?;
+                }
+                Ordering::Equal => {
+                    let (key, l_values) = l_entry.take().unwrap();
+                    let (_, mut r_values) = r_entry.take().unwrap();
+                    if ty.is_multi {
+                        for value in l_values.iter().filter(|v| !r_values.contains(v)) {
+                            changes.remove(&key, value)?;
+                        }
+                        for value in r_values.iter().filter(|v| !l_values.contains(v)) {
+                            changes.insert(&key, None, value)?;
+                        }
+                    } else {
+                        let old = l_values.into_iter().next();
+                        changes.insert(&key, old, &r_values.remove(0))?;
+                    }
+                    l_entry = next(&mut l_entries, &l_schema)?;
+                    r_entry = next(&mut r_entries, &r_schema)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names of the tables of a database, multimaps included
+fn tables(db: &DB) -> Result<BTreeSet<String>> {
+    let r = db.begin_read()?;
+    let tables = r.list_tables()?.map(|t| t.name().to_string());
+    let multimaps = r.list_multimap_tables()?.map(|t| t.name().to_string());
+    Ok(tables.chain(multimaps).collect())
+}
+
+/// Entries of a table whose types are entered, decoded as they are read
+fn entries(db: &DB, table: &str) -> Result<Entries> {
+    let r = db.begin_read()?;
+    if r.list_multimap_tables()?.any(|t| t.name() == table) {
+        let table = r.open_multimap_table(MultimapTableDefinition::<K, V>::new(table))?;
+        Ok(Box::new(table.range::<Value>(..)?.map(|entry| {
+            let (key, values) = entry?;
+            let values = values.map(|v| Ok(v?.value())).collect::<Result<_>>()?;
+            Ok((key.value(), values))
+        })))
+    } else {
+        let table = r.open_table(TableDefinition::<K, V>::new(table))?;
+        Ok(Box::new(table.range::<Value>(..)?.map(|entry| {
+            let (key, value) = entry?;
+            Ok((key.value(), vec![value.value()]))
+        })))
+    }
+}
@@ -0,0 +1,175 @@
+use eyre::{OptionExt, Result, WrapErr, eyre};
+use redb::{MultimapTable, MultimapTableDefinition, Table, TableDefinition, TypeName};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::{Cell, RefCell},
+    io::{BufRead, BufReader},
+    thread::LocalKey,
+};
+
+use crate::{
+    DB, WARNING,
+    parser::{self, TypeDesc},
+    transcode::{K, K_NAME, K_TREE, K_WIDTH, V, V_NAME, V_TREE, V_WIDTH, check_key, check_value},
+    typename::{self, Class},
+};
+
+/// Line of a dump, a table header followed by its entries, one line per value of multimaps
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Line {
+    Table(Header),
+    Entry {
+        key: serde_json::Value,
+        value: serde_json::Value,
+    },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct Header {
+    pub table: String,
+    pub multimap: bool,
+    pub key: Schema,
+    pub value: Schema,
+}
+
+/// Key or value type as stored in the table definition
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct Schema {
+    pub name: String,
+    pub class: Class,
+    pub width: Option<usize>,
+    /// Entries are hex strings, because the type is unknown or `--raw` was used
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw: bool,
+}
+
+impl Header {
+    /// Header of the table whose types are currently set in [`crate::transcode`]
+    pub fn current(table: &str, multimap: bool) -> Self {
+        let schema = |name: &'static LocalKey<RefCell<TypeName>>,
+                      width: &'static LocalKey<Cell<Option<usize>>>,
+                      tree: &'static LocalKey<RefCell<Option<TypeDesc>>>| {
+            let name = name.with_borrow(Clone::clone);
+            tree.with_borrow(|t| Schema::new(&name, width.get(), t.as_ref().unwrap()))
+        };
+        Self {
+            table: table.to_string(),
+            multimap,
+            key: schema(&K_NAME, &K_WIDTH, &K_TREE),
+            value: schema(&V_NAME, &V_WIDTH, &V_TREE),
+        }
+    }
+}
+
+impl Schema {
+    fn new(ty: &TypeName, width: Option<usize>, tree: &TypeDesc) -> Self {
+        let class = typename::classify(ty).unwrap_or_else(|| {
+            eprintln!("{WARNING} Can't reproduce type name {ty:?}, it won't be restored as is");
+            Class::Internal
+        });
+        Self {
+            name: ty.name().to_string(),
+            class,
+            width,
+            raw: matches!(tree, TypeDesc::Raw { .. }),
+        }
+    }
+
+    fn tree(&self) -> Result<TypeDesc> {
+        let tree = parser::parse_tree(&self.name)
+            .and_then(|t| parser::check_width(&t, self.width).map(|_| t));
+        Ok(match tree {
+            Ok(tree) if self.raw => tree.into_raw(),
+            Err(_) if self.raw => TypeDesc::Raw {
+                ty: None,
+                width: self.width,
+            },
+            tree => tree?,
+        })
+    }
+}
+
+enum Open<'a> {
+    Table(Table<'a, K, V>),
+    Multimap(MultimapTable<'a, K, V>),
+}
+
+/// Recreate the tables of a dump with their exact type names and widths, in one transaction
+pub fn restore(db: &DB, input: Option<&str>) -> Result<()> {
+    let input: Box<dyn BufRead> = match input {
+        None | Some("-") => Box::new(std::io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(
+            std::fs::File::open(path).wrap_err_with(|| format!("Can't open {path:?}"))?,
+        )),
+    };
+
+    let w = db.begin_write()?;
+    let mut table = None;
+    let mut restored = None;
+    let report = |restored: Option<(String, usize)>| {
+        if let Some((name, count)) = restored {
+            eprintln!("Restored {count} entries into {name:?}");
+        }
+    };
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let err = || format!("Error restoring line {}", i + 1);
+
+        match serde_json::from_str(&line).wrap_err_with(err)? {
+            Line::Table(header) => {
+                drop(table.take());
+                report(restored.replace((header.table.clone(), 0)));
+
+                let (k_tree, v_tree) = (header.key.tree(), header.value.tree());
+                let (k_tree, v_tree) = (k_tree.wrap_err_with(err)?, v_tree.wrap_err_with(err)?);
+                // Entries are inserted in the order of the original `Key::compare`, or not at all
+                let unordered = |schema: &Schema, what| {
+                    let (name, table) = (&schema.name, &header.table);
+                    eyre!("{what} type {name:?} of table {table:?} can't be ordered")
+                };
+                if !parser::can_order(&k_tree) {
+                    return Err(unordered(&header.key, "Key")).wrap_err_with(err);
+                }
+                if header.multimap && !parser::can_order(&v_tree) {
+                    return Err(unordered(&header.value, "Value")).wrap_err_with(err);
+                }
+                K_NAME.set(typename::type_name(&header.key.name, header.key.class)?);
+                K_WIDTH.set(header.key.width);
+                K_TREE.set(Some(k_tree));
+                V_NAME.set(typename::type_name(&header.value.name, header.value.class)?);
+                V_WIDTH.set(header.value.width);
+                V_TREE.set(Some(v_tree));
+
+                table = Some(if header.multimap {
+                    let def = MultimapTableDefinition::<K, V>::new(&header.table);
+                    Open::Multimap(w.open_multimap_table(def)?)
+                } else {
+                    Open::Table(w.open_table(TableDefinition::<K, V>::new(&header.table))?)
+                });
+            }
+            Line::Entry { key, value } => {
+                // Keys and values are checked against the types of the table
+                let open = table.as_mut().ok_or_eyre("Entry before the first table");
+                let open = open.wrap_err_with(err)?;
+                let (k, v) = (check_key(key), check_value(value));
+                let (k, v) = (k.wrap_err_with(err)?, v.wrap_err_with(err)?);
+                match open {
+                    Open::Table(table) => drop(table.insert(k, v)?),
+                    Open::Multimap(table) => drop(table.insert(k, v)?),
+                }
+                if let Some((_, count)) = &mut restored {
+                    *count += 1;
+                }
+            }
+        }
+    }
+    drop(table);
+    report(restored);
+    w.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! Editing values as pretty JSON in an external editor
+
+use eyre::{OptionExt, Result, WrapErr, bail, eyre};
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Open a value in `editor` until `check` accepts it, returns `None` if it wasn't changed
+pub fn edit(
+    editor: &str,
+    value: &Value,
+    check: impl Fn(&Value) -> Result<()>,
+) -> Result<Option<Value>> {
+    let mut text = serde_json::to_string_pretty(value)? + "\n";
+    let (path, mut file) = create_temp()?;
+
+    let edit = || {
+        file.write_all(text.as_bytes())?;
+        drop(file);
+        // The next attempt starts from the edited text, already in the file
+        loop {
+            run(editor, &path)?;
+            text = std::fs::read_to_string(&path)?;
+
+            let edited = serde_json::from_str(&text).wrap_err("Invalid JSON");
+            match edited.and_then(|v| check(&v).map(|_| v)) {
+                Ok(edited) => return Ok((edited != *value).then_some(edited)),
+                Err(err) => {
+                    eprintln!("Error: {err:#}");
+                    if !ask("Edit again? [Y/n] ")? {
+                        return Err(eyre!("Edit cancelled"));
+                    }
+                }
+            }
+        }
+    };
+    let res = edit();
+    std::fs::remove_file(&path).ok();
+    res
+}
+
+/// Create a new file only the user can read, so values aren't exposed to other users of the
+/// temp dir, and a link planted at its name isn't followed
+fn create_temp() -> Result<(PathBuf, File)> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let pid = std::process::id();
+    for i in 0.. {
+        let path = std::env::temp_dir().join(format!("redb-cli-{pid}-{i}.json"));
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err).wrap_err("Can't create a temporary file"),
+        }
+    }
+    unreachable!()
+}
+
+fn run(editor: &str, path: &Path) -> Result<()> {
+    let words = shlex::split(editor).filter(|w| !w.is_empty());
+    let words = words.ok_or_eyre("Can't parse $EDITOR")?;
+    let status = Command::new(&words[0])
+        .args(&words[1..])
+        .arg(path)
+        .status()
+        .wrap_err_with(|| format!("Can't run {editor:?}"))?;
+    if !status.success() {
+        bail!("{editor:?} exited with {status}");
+    }
+    Ok(())
+}
+
+/// Yes is the default, end of input is no
+fn ask(question: &str) -> Result<bool> {
+    eprint!("{question}");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        eprintln!();
+        return Ok(false);
+    }
+    Ok(matches!(answer.trim(), "" | "y" | "Y" | "yes"))
+}
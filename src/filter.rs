@@ -0,0 +1,654 @@
+//! Filters over entries in a subset of jq, evaluated against `{"key": .., "value": ..}`.
+//!
+//! Supported are paths (`.a.b`, `.[0]`, `.["a b"]`), literals and arrays, `|`, `//`, `and`,
+//! `or`, comparisons, arithmetic, and a few builtins like `select`, `has` and `length`.
+//! Filters yield one value or none (`empty`), there are no generators like `.[]` or `,`.
+
+use eyre::{Result, bail, eyre};
+use pest::{
+    Parser,
+    iterators::Pair,
+    pratt_parser::{Assoc, Op, PrattParser},
+};
+use pest_derive::Parser;
+use serde_json::{Map, Number, Value};
+use std::{borrow::Cow, cmp::Ordering, sync::LazyLock};
+
+use crate::parser::{TypeDesc, as_raw};
+
+#[derive(Parser)]
+#[grammar = "src/filter.pest"]
+struct Grammar;
+
+static PRATT: LazyLock<PrattParser<Rule>> = LazyLock::new(|| {
+    PrattParser::new()
+        .op(Op::infix(Rule::Alt, Assoc::Right))
+        .op(Op::infix(Rule::Or, Assoc::Left))
+        .op(Op::infix(Rule::And, Assoc::Left))
+        .op(Op::infix(Rule::Eq, Assoc::Left)
+            | Op::infix(Rule::Ne, Assoc::Left)
+            | Op::infix(Rule::Le, Assoc::Left)
+            | Op::infix(Rule::Ge, Assoc::Left)
+            | Op::infix(Rule::Lt, Assoc::Left)
+            | Op::infix(Rule::Gt, Assoc::Left))
+        .op(Op::infix(Rule::Add, Assoc::Left) | Op::infix(Rule::Sub, Assoc::Left))
+        .op(Op::infix(Rule::Mul, Assoc::Left)
+            | Op::infix(Rule::Div, Assoc::Left)
+            | Op::infix(Rule::Rem, Assoc::Left))
+        .op(Op::prefix(Rule::Neg))
+});
+
+static NULL: Value = Value::Null;
+
+/// Compiled `--filter` expression
+#[derive(Debug, Clone)]
+pub struct Filter(Ast);
+
+#[derive(Debug, Clone)]
+enum Ast {
+    Identity,
+    Literal(Value),
+    /// Target and index, both evaluated against the input
+    Index(Box<Ast>, Box<Ast>),
+    Pipe(Box<Ast>, Box<Ast>),
+    /// Operator rule, left and right operands
+    Binary(Rule, Box<Ast>, Box<Ast>),
+    Neg(Box<Ast>),
+    Array(Vec<Ast>),
+    Call(Builtin, Vec<Ast>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Builtin {
+    Not,
+    Length,
+    Keys,
+    Type,
+    ToString,
+    ToNumber,
+    AsciiDowncase,
+    AsciiUpcase,
+    Any,
+    All,
+    Empty,
+    Select,
+    Has,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Map,
+}
+
+impl Filter {
+    pub fn new(expr: &str) -> Result<Self> {
+        let pipe = Grammar::parse(Rule::Filter, expr)?.next().unwrap();
+        Ok(Self(Ast::new(pipe)?))
+    }
+
+    /// Whether the filter yields a value other than `false` and `null` for the entry
+    pub fn matches(&self, key: &mut Value, value: &mut Value) -> Result<bool> {
+        // The entry is assembled from the key and value without copying them
+        let mut entry = Map::new();
+        entry.insert("key".into(), std::mem::take(key));
+        entry.insert("value".into(), std::mem::take(value));
+        let entry = Value::Object(entry);
+        let res = self
+            .0
+            .eval(&entry)
+            .map(|val| val.is_some_and(|val| truthy(&val)));
+
+        let Value::Object(mut entry) = entry else {
+            unreachable!()
+        };
+        *key = entry.remove("key").unwrap();
+        *value = entry.remove("value").unwrap();
+        res
+    }
+
+    /// Value the filter yields, if any
+    pub fn eval(&self, input: &Value) -> Result<Option<Value>> {
+        Ok(self.0.eval(input)?.map(Cow::into_owned))
+    }
+}
+
+impl Ast {
+    fn new(pair: Pair<Rule>) -> Result<Self> {
+        Ok(match pair.as_rule() {
+            Rule::Pipe => {
+                let mut exprs = pair.into_inner().map(Self::new);
+                let first = exprs.next().unwrap()?;
+                exprs.try_fold(first, |l, r| Ok::<_, eyre::Report>(Self::pipe(l, r?)))?
+            }
+            Rule::Expr => PRATT
+                .map_primary(Self::new)
+                .map_prefix(|_, val| Ok(Self::Neg(Box::new(val?))))
+                .map_infix(|l, op, r| Ok(Self::Binary(op.as_rule(), Box::new(l?), Box::new(r?))))
+                .parse(pair.into_inner())?,
+            Rule::Postfix => {
+                let mut inner = pair.into_inner();
+                let term = Self::new(inner.next().unwrap())?;
+                inner.try_fold(term, |term, index| Self::index(term, index))?
+            }
+            Rule::Path => match pair.into_inner().next() {
+                None => Self::Identity,
+                Some(field) => Self::index(Self::Identity, field)?,
+            },
+            Rule::Number => Self::Literal(Value::Number(serde_json::from_str(pair.as_str())?)),
+            Rule::String | Rule::Literal => Self::Literal(serde_json::from_str(pair.as_str())?),
+            Rule::Array => Self::Array(pair.into_inner().map(Self::new).collect::<Result<_>>()?),
+            Rule::Call => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str();
+                let args = inner.map(Self::new).collect::<Result<Vec<_>>>()?;
+                let (builtin, arity) = match name {
+                    "not" => (Builtin::Not, 0),
+                    "length" => (Builtin::Length, 0),
+                    "keys" => (Builtin::Keys, 0),
+                    "type" => (Builtin::Type, 0),
+                    "tostring" => (Builtin::ToString, 0),
+                    "tonumber" => (Builtin::ToNumber, 0),
+                    "ascii_downcase" => (Builtin::AsciiDowncase, 0),
+                    "ascii_upcase" => (Builtin::AsciiUpcase, 0),
+                    "any" => (Builtin::Any, 0),
+                    "all" => (Builtin::All, 0),
+                    "empty" => (Builtin::Empty, 0),
+                    "select" => (Builtin::Select, 1),
+                    "has" => (Builtin::Has, 1),
+                    "contains" => (Builtin::Contains, 1),
+                    "startswith" => (Builtin::StartsWith, 1),
+                    "endswith" => (Builtin::EndsWith, 1),
+                    "map" => (Builtin::Map, 1),
+                    _ => bail!("Unknown function {name}/{}", args.len()),
+                };
+                if args.len() != arity {
+                    bail!("Unknown function {name}/{}", args.len());
+                }
+                Self::Call(builtin, args)
+            }
+            rule => unreachable!("{rule:?}"),
+        })
+    }
+
+    fn pipe(l: Self, r: Self) -> Self {
+        Self::Pipe(Box::new(l), Box::new(r))
+    }
+
+    /// Index the term with a `.field` or `[expr]`
+    fn index(term: Self, pair: Pair<Rule>) -> Result<Self> {
+        let index = match pair.as_rule() {
+            Rule::DotField => {
+                let field = pair.into_inner().next().unwrap();
+                match field.as_rule() {
+                    Rule::Ident => Self::Literal(Value::String(field.as_str().into())),
+                    _ => Self::new(field)?,
+                }
+            }
+            Rule::Bracket => Self::new(pair.into_inner().next().unwrap())?,
+            rule => unreachable!("{rule:?}"),
+        };
+        Ok(Self::Index(Box::new(term), Box::new(index)))
+    }
+
+    fn eval<'a>(&self, input: &'a Value) -> Result<Option<Cow<'a, Value>>> {
+        Ok(Some(match self {
+            Self::Identity => Cow::Borrowed(input),
+            Self::Literal(val) => Cow::Owned(val.clone()),
+            Self::Index(term, index) => {
+                let (Some(term), Some(index)) = (term.eval(input)?, index.eval(input)?) else {
+                    return Ok(None);
+                };
+                match term {
+                    Cow::Borrowed(term) => Cow::Borrowed(get(term, &index)?),
+                    Cow::Owned(term) => Cow::Owned(get(&term, &index)?.clone()),
+                }
+            }
+            Self::Pipe(l, r) => match l.eval(input)? {
+                None => return Ok(None),
+                Some(Cow::Borrowed(l)) => return r.eval(l),
+                Some(Cow::Owned(l)) => return Ok(r.eval(&l)?.map(|v| Cow::Owned(v.into_owned()))),
+            },
+            Self::Binary(op @ (Rule::And | Rule::Or | Rule::Alt), l, r) => {
+                let Some(l) = l.eval(input)? else {
+                    return Ok(None);
+                };
+                match (op, truthy(&l)) {
+                    (Rule::And, false) => Cow::Owned(Value::Bool(false)),
+                    (Rule::Or, true) => Cow::Owned(Value::Bool(true)),
+                    (Rule::Alt, true) => l,
+                    (Rule::Alt, false) => return r.eval(input),
+                    _ => match r.eval(input)? {
+                        None => return Ok(None),
+                        Some(r) => Cow::Owned(Value::Bool(truthy(&r))),
+                    },
+                }
+            }
+            Self::Binary(op, l, r) => {
+                let (Some(l), Some(r)) = (l.eval(input)?, r.eval(input)?) else {
+                    return Ok(None);
+                };
+                Cow::Owned(binary(*op, &l, &r)?)
+            }
+            Self::Neg(val) => {
+                let Some(val) = val.eval(input)? else {
+                    return Ok(None);
+                };
+                Cow::Owned(binary(Rule::Sub, &Value::from(0), &val)?)
+            }
+            Self::Array(items) => {
+                let mut vec = Vec::with_capacity(items.len());
+                for item in items {
+                    vec.extend(item.eval(input)?.map(Cow::into_owned));
+                }
+                Cow::Owned(Value::Array(vec))
+            }
+            Self::Call(builtin, args) => return call(*builtin, args, input),
+        }))
+    }
+}
+
+fn call<'a>(builtin: Builtin, args: &[Ast], input: &'a Value) -> Result<Option<Cow<'a, Value>>> {
+    let arg = match args.first() {
+        Some(arg) => match arg.eval(input)? {
+            Some(arg) => Some(arg),
+            None => return Ok(None),
+        },
+        None => None,
+    };
+    let arg = || arg.as_deref().unwrap();
+    let strings = |name: &str| match (input, arg()) {
+        (Value::String(s), Value::String(arg)) => Ok((s, arg)),
+        _ => Err(eyre!("{name}() requires string inputs")),
+    };
+    Ok(Some(Cow::Owned(match builtin {
+        Builtin::Not => Value::Bool(!truthy(input)),
+        Builtin::Length => match input {
+            Value::Null => Value::from(0),
+            Value::Bool(_) => bail!("{} has no length", describe(input)),
+            Value::Number(n) => match Num::new(n) {
+                Num::Int(n) => Num::Int(n.abs()),
+                Num::Float(n) => Num::Float(n.abs()),
+            }
+            .into_value(),
+            Value::String(s) => Value::from(s.chars().count()),
+            Value::Array(vec) => Value::from(vec.len()),
+            Value::Object(map) => Value::from(map.len()),
+        },
+        Builtin::Keys => match input {
+            Value::Object(map) => map.keys().cloned().map(Value::String).collect(),
+            Value::Array(vec) => (0..vec.len()).map(Value::from).collect(),
+            _ => bail!("{} has no keys", describe(input)),
+        },
+        Builtin::Type => Value::String(type_name(input).into()),
+        Builtin::ToString => match input {
+            Value::String(_) => input.clone(),
+            _ => Value::String(serde_json::to_string(input)?),
+        },
+        Builtin::ToNumber => match input {
+            Value::Number(_) => input.clone(),
+            Value::String(s) => Value::Number(
+                s.trim()
+                    .parse()
+                    .map_err(|_| eyre!("Cannot parse {} as a number", describe(input)))?,
+            ),
+            _ => bail!("{} cannot be parsed as a number", describe(input)),
+        },
+        Builtin::AsciiDowncase | Builtin::AsciiUpcase => match input {
+            Value::String(s) if matches!(builtin, Builtin::AsciiDowncase) => {
+                Value::String(s.to_ascii_lowercase())
+            }
+            Value::String(s) => Value::String(s.to_ascii_uppercase()),
+            _ => bail!("{} cannot be case-converted", describe(input)),
+        },
+        Builtin::Any | Builtin::All => {
+            let vec: Vec<_> = match input {
+                Value::Array(vec) => vec.iter().collect(),
+                Value::Object(map) => map.values().collect(),
+                _ => bail!("Cannot iterate over {}", describe(input)),
+            };
+            match builtin {
+                Builtin::Any => Value::Bool(vec.into_iter().any(truthy)),
+                _ => Value::Bool(vec.into_iter().all(truthy)),
+            }
+        }
+        Builtin::Empty => return Ok(None),
+        Builtin::Select => match truthy(arg()) {
+            true => return Ok(Some(Cow::Borrowed(input))),
+            false => return Ok(None),
+        },
+        Builtin::Has => Value::Bool(match (input, arg()) {
+            (Value::Object(map), Value::String(key)) => map.contains_key(key),
+            (Value::Array(vec), Value::Number(n)) => {
+                n.as_u64().is_some_and(|i| i < vec.len() as u64)
+            }
+            (_, arg) => bail!(
+                "Cannot check whether {} has {}",
+                describe(input),
+                describe(arg)
+            ),
+        }),
+        Builtin::Contains => Value::Bool(contains(input, arg())?),
+        Builtin::StartsWith => {
+            let (s, prefix) = strings("startswith")?;
+            Value::Bool(s.starts_with(prefix.as_str()))
+        }
+        Builtin::EndsWith => {
+            let (s, suffix) = strings("endswith")?;
+            Value::Bool(s.ends_with(suffix.as_str()))
+        }
+        Builtin::Map => {
+            let items: Vec<_> = match input {
+                Value::Array(vec) => vec.iter().collect(),
+                Value::Object(map) => map.values().collect(),
+                _ => bail!("Cannot iterate over {}", describe(input)),
+            };
+            let mut vec = Vec::with_capacity(items.len());
+            for item in items {
+                vec.extend(args[0].eval(item)?.map(Cow::into_owned));
+            }
+            Value::Array(vec)
+        }
+    })))
+}
+
+/// Whether a value passes `select`, `and` and `or`
+fn truthy(val: &Value) -> bool {
+    !matches!(val, Value::Null | Value::Bool(false))
+}
+
+fn type_name(val: &Value) -> &'static str {
+    match val {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Type and value for error messages, like jq's `number (1)`
+fn describe(val: &Value) -> String {
+    let mut json = val.to_string();
+    if json.len() > 40 {
+        let end = (0..=37).rev().find(|&i| json.is_char_boundary(i)).unwrap();
+        json.truncate(end);
+        json.push_str("...");
+    }
+    format!("{} ({json})", type_name(val))
+}
+
+fn get<'a>(term: &'a Value, index: &Value) -> Result<&'a Value> {
+    Ok(match (term, index) {
+        (Value::Object(map), Value::String(key)) => map.get(key).unwrap_or(&NULL),
+        (Value::Array(vec), Value::Number(n)) => {
+            let i = n
+                .as_i64()
+                .ok_or_else(|| eyre!("Cannot index array with {n}"))?;
+            let i = if i < 0 { i + vec.len() as i64 } else { i };
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| vec.get(i))
+                .unwrap_or(&NULL)
+        }
+        (Value::Null, Value::String(_) | Value::Number(_)) => &NULL,
+        (_, Value::String(key)) => bail!("Cannot index {} with {key:?}", type_name(term)),
+        _ => bail!("Cannot index {} with {}", type_name(term), type_name(index)),
+    })
+}
+
+/// Number as an integer when it is one, so large integers are compared exactly
+enum Num {
+    Int(i128),
+    Float(f64),
+}
+
+impl Num {
+    fn new(n: &Number) -> Self {
+        match n.as_i128() {
+            Some(n) => Num::Int(n),
+            None => Num::Float(n.as_f64().unwrap_or(f64::NAN)),
+        }
+    }
+
+    fn float(&self) -> f64 {
+        match *self {
+            Num::Int(n) => n as f64,
+            Num::Float(n) => n,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Num::Int(n) => Number::from_i128(n).map_or(Value::Null, Value::Number),
+            Num::Float(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+        }
+    }
+}
+
+/// Order of jq, `null < false < true < numbers < strings < arrays < objects`
+fn compare(l: &Value, r: &Value) -> Ordering {
+    let rank = |val: &Value| match val {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => match (Num::new(l), Num::new(r)) {
+            (Num::Int(l), Num::Int(r)) => l.cmp(&r),
+            (l, r) => l.float().total_cmp(&r.float()),
+        },
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Array(l), Value::Array(r)) => {
+            let first = l
+                .iter()
+                .zip(r)
+                .map(|(l, r)| compare(l, r))
+                .find(|o| o.is_ne());
+            first.unwrap_or_else(|| l.len().cmp(&r.len()))
+        }
+        // Keys are compared first, then values in the order of keys
+        (Value::Object(l), Value::Object(r)) => l.keys().cmp(r.keys()).then_with(|| {
+            let values = l.values().zip(r.values()).map(|(l, r)| compare(l, r));
+            values
+                .into_iter()
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        }),
+        _ => rank(l).cmp(&rank(r)),
+    }
+}
+
+fn binary(op: Rule, l: &Value, r: &Value) -> Result<Value> {
+    let ordering = || compare(l, r);
+    Ok(match op {
+        Rule::Eq => Value::Bool(ordering().is_eq()),
+        Rule::Ne => Value::Bool(ordering().is_ne()),
+        Rule::Lt => Value::Bool(ordering().is_lt()),
+        Rule::Le => Value::Bool(ordering().is_le()),
+        Rule::Gt => Value::Bool(ordering().is_gt()),
+        Rule::Ge => Value::Bool(ordering().is_ge()),
+        _ => arithmetic(op, l, r)?,
+    })
+}
+
+fn arithmetic(op: Rule, l: &Value, r: &Value) -> Result<Value> {
+    Ok(match (op, l, r) {
+        (Rule::Add, Value::Null, val) | (Rule::Add, val, Value::Null) => val.clone(),
+        (_, Value::Number(l), Value::Number(r)) => {
+            let (l, r) = (Num::new(l), Num::new(r));
+            if let (Num::Int(l), Num::Int(r)) = (&l, &r) {
+                let int = match op {
+                    Rule::Add => l.checked_add(*r),
+                    Rule::Sub => l.checked_sub(*r),
+                    Rule::Mul => l.checked_mul(*r),
+                    Rule::Rem if *r == 0 => {
+                        bail!("{l} and {r} cannot be divided because the divisor is zero")
+                    }
+                    // Only i128::MIN by -1 overflows
+                    Rule::Div | Rule::Rem if *r != 0 && l.checked_rem(*r).is_none() => {
+                        bail!("{l} and {r} cannot be divided because the result overflows")
+                    }
+                    Rule::Div if *r != 0 && l % r == 0 => Some(l / r),
+                    Rule::Rem => Some(l % r),
+                    _ => None,
+                };
+                if let Some(int) = int {
+                    return Ok(Num::Int(int).into_value());
+                }
+            }
+            let (l, r) = (l.float(), r.float());
+            Num::Float(match op {
+                Rule::Add => l + r,
+                Rule::Sub => l - r,
+                Rule::Mul => l * r,
+                Rule::Div if r == 0.0 => {
+                    bail!("{l} and {r} cannot be divided because the divisor is zero")
+                }
+                Rule::Div => l / r,
+                // Like jq, the remainder of the operands truncated to integers
+                _ => match (l as i128).checked_rem(r as i128) {
+                    Some(rem) => rem as f64,
+                    None if r as i128 == 0 => {
+                        bail!("{l} and {r} cannot be divided because the divisor is zero")
+                    }
+                    None => bail!("{l} and {r} cannot be divided because the result overflows"),
+                },
+            })
+            .into_value()
+        }
+        (Rule::Add, Value::String(l), Value::String(r)) => Value::String(format!("{l}{r}")),
+        (Rule::Add, Value::Array(l), Value::Array(r)) => {
+            Value::Array(l.iter().chain(r).cloned().collect())
+        }
+        (Rule::Add, Value::Object(l), Value::Object(r)) => {
+            let mut map = l.clone();
+            map.extend(r.clone());
+            Value::Object(map)
+        }
+        (Rule::Sub, Value::Array(l), Value::Array(r)) => {
+            let kept = l
+                .iter()
+                .filter(|l| !r.iter().any(|r| compare(l, r).is_eq()));
+            Value::Array(kept.cloned().collect())
+        }
+        _ => {
+            let verb = match op {
+                Rule::Add => "added",
+                Rule::Sub => "subtracted",
+                Rule::Mul => "multiplied",
+                Rule::Div => "divided",
+                _ => "divided (remainder)",
+            };
+            bail!("{} and {} cannot be {verb}", describe(l), describe(r))
+        }
+    })
+}
+
+/// Containment of jq, substrings for strings and subsets for arrays and objects
+fn contains(l: &Value, r: &Value) -> Result<bool> {
+    Ok(match (l, r) {
+        (Value::String(l), Value::String(r)) => l.contains(r.as_str()),
+        // Elements of different types don't contain each other
+        (Value::Array(l), Value::Array(r)) => r
+            .iter()
+            .all(|r| l.iter().any(|l| contains(l, r).unwrap_or(false))),
+        (Value::Object(l), Value::Object(r)) => {
+            for (key, r) in r {
+                if !l
+                    .get(key)
+                    .map(|l| contains(l, r))
+                    .transpose()?
+                    .unwrap_or(false)
+                {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        _ if type_name(l) == type_name(r) => compare(l, r).is_eq(),
+        _ => bail!(
+            "{} and {} cannot have their containment checked",
+            describe(l),
+            describe(r)
+        ),
+    })
+}
+
+/// Struct fields of values to keep with `--fields`, e.g. `address.city`
+#[derive(Debug, Clone, Default)]
+pub struct Fields(Vec<Vec<String>>);
+
+impl Fields {
+    pub fn new(fields: &[String]) -> Self {
+        Self(
+            fields
+                .iter()
+                .map(|f| f.split('.').map(str::to_string).collect())
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check that the fields exist in the value type
+    pub fn check(&self, ty: &TypeDesc) -> Result<()> {
+        for path in &self.0 {
+            let mut ty = ty;
+            for seg in path {
+                let TypeDesc::Struct { fields, .. } = ty else {
+                    bail!(
+                        "Can't select {:?} of {ty}, only struct fields can be selected",
+                        path.join(".")
+                    );
+                };
+                ty = fields
+                    .iter()
+                    .find(|(name, _)| name == seg)
+                    .map(|(_, ty)| ty)
+                    .ok_or_else(|| eyre!("No field {:?} in {ty}", path.join(".")))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Value with only the selected fields, values that failed to decode are kept as is
+    pub fn project(&self, val: Value) -> Value {
+        if self.0.is_empty() || as_raw(&val).is_some() {
+            return val;
+        }
+        let mut res = Map::new();
+        for path in &self.0 {
+            copy(&val, &mut res, path);
+        }
+        Value::Object(res)
+    }
+
+    /// Whether a field at the path is kept, either selected or a part of a selected field
+    pub fn keeps(&self, path: &[String]) -> bool {
+        self.0.is_empty()
+            || self
+                .0
+                .iter()
+                .any(|f| path.starts_with(f) || f.starts_with(path))
+    }
+}
+
+fn copy(src: &Value, dst: &mut Map<String, Value>, path: &[String]) {
+    let Some((seg, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(src) = src.get(seg) else {
+        return;
+    };
+    if rest.is_empty() {
+        dst.insert(seg.clone(), src.clone());
+    } else if let Value::Object(dst) = dst.entry(seg).or_insert(Value::Object(Map::new())) {
+        copy(src, dst, rest);
+    }
+}
@@ -1,10 +1,32 @@
 use eyre::{Result, bail};
-use redb::{Database, ReadOnlyDatabase, ReadTransaction, ReadableDatabase, WriteTransaction};
+use redb::{
+    Database, MultimapTableHandle, ReadOnlyDatabase, ReadTransaction, ReadableDatabase,
+    TableHandle, TypeName, WriteTransaction,
+};
 use std::collections::BTreeMap;
 
+use crate::{
+    cli::CliArgs,
+    parser::TypeDesc,
+    transcode::{K_NAME, K_TREE, K_WIDTH, V_NAME, V_TREE, V_WIDTH, set_schema},
+};
+
+pub mod batch;
+pub mod changes;
 pub mod cli;
+pub mod compare;
+pub mod copy;
+pub mod delete;
+pub mod diff;
+pub mod dump;
+pub mod edit;
+pub mod filter;
+pub mod load;
 pub mod parser;
+pub mod shell;
 pub mod transcode;
+pub mod tui;
+pub mod typename;
 pub mod utils;
 
 pub mod print;
@@ -14,12 +36,42 @@ pub mod process {
     include!(concat!(env!("OUT_DIR"), "/process.rs"));
 }
 
+#[cfg(test)]
+mod test_batch;
+#[cfg(test)]
+mod test_changes;
+#[cfg(test)]
+mod test_cli;
+#[cfg(test)]
+mod test_copy;
+#[cfg(test)]
+mod test_diff;
+#[cfg(test)]
+mod test_dump;
+#[cfg(test)]
+mod test_edit;
+#[cfg(test)]
+mod test_enums;
+#[cfg(test)]
+mod test_filter;
 #[cfg(test)]
 mod test_ordering;
 #[cfg(test)]
+mod test_output;
+#[cfg(test)]
+#[allow(clippy::crate_in_macro_def, clippy::byte_char_slices)]
 mod test_parser;
 #[cfg(test)]
+mod test_savepoint;
+#[cfg(test)]
+mod test_shell;
+#[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod test_structs;
+#[cfg(test)]
+mod test_tui;
+#[cfg(test)]
+mod test_typename;
 
 pub const WARNING: &str = "\x1b[1m\x1b[33mwarning\x1b(B\x1b[m:";
 
@@ -53,18 +105,146 @@ impl DB {
             DB::RW(db) => Ok(db.compact()?),
         }
     }
+    /// Save the current state until the savepoint is deleted, returning its id
+    pub fn persistent_savepoint(&self) -> Result<u64> {
+        let w = self.begin_write()?;
+        let id = w.persistent_savepoint()?;
+        w.commit()?;
+        Ok(id)
+    }
+    /// Ids of persistent savepoints, oldest first
+    pub fn list_persistent_savepoints(&self) -> Result<Vec<u64>> {
+        // Savepoints can only be listed in a write transaction
+        let w = self.begin_write()?;
+        let mut ids: Vec<_> = w.list_persistent_savepoints()?.collect();
+        w.abort()?;
+        ids.sort();
+        Ok(ids)
+    }
+    /// Roll back to a persistent savepoint, which drops the savepoints created after it
+    pub fn restore_savepoint(&self, id: u64) -> Result<()> {
+        let mut w = self.begin_write()?;
+        if !w.list_persistent_savepoints()?.any(|i| i == id) {
+            bail!("No such savepoint {id}");
+        }
+        let savepoint = w.get_persistent_savepoint(id)?;
+        w.restore_savepoint(&savepoint)?;
+        drop(savepoint);
+        w.commit()?;
+        Ok(())
+    }
+    pub fn delete_persistent_savepoint(&self, id: u64) -> Result<()> {
+        let w = self.begin_write()?;
+        if !w.delete_persistent_savepoint(id)? {
+            bail!("No such savepoint {id}");
+        }
+        w.commit()?;
+        Ok(())
+    }
 }
 
+#[derive(Clone, Copy)]
 pub struct KVType<T> {
     pub k_ty: T,
     pub v_ty: T,
     pub is_multi: bool,
 }
 
+/// Types of a table resolved once, restored into the `K_*` and `V_*` thread-locals before it's
+/// opened again
+pub struct Schema {
+    k: (TypeName, Option<usize>, TypeDesc),
+    v: (TypeName, Option<usize>, TypeDesc),
+    ty: KVType<&'static String>,
+}
+
+impl Schema {
+    /// Tables that don't exist yet are multimaps if `args.multimap` is set
+    pub fn resolve(args: &CliArgs, db: &DB, table: &str) -> Result<Self> {
+        let r = db.begin_read()?;
+        let exists = r.list_tables()?.any(|t| t.name() == table);
+        let is_multi =
+            r.list_multimap_tables()?.any(|t| t.name() == table) || (!exists && args.multimap);
+        drop(r);
+
+        let ty = if is_multi {
+            process_multimap::resolve_multimap_types(args, db, table)?;
+            process_multimap::parse_multimap_types(args, table)
+        } else {
+            process::resolve_types(args, db, table)?;
+            process::parse_types(args, table)
+        };
+
+        Ok(Self::current(ty))
+    }
+
+    /// Types given by the user, e.g. `u64 -> String`, for a table that may not exist yet
+    pub fn parse(args: &CliArgs, table: &str, schema: &str, is_multi: bool) -> Result<Self> {
+        set_schema(schema)?;
+        let ty = if is_multi {
+            process_multimap::parse_multimap_types(args, table)
+        } else {
+            process::parse_types(args, table)
+        };
+
+        Ok(Self::current(ty))
+    }
+
+    /// Types currently in the thread-locals
+    fn current(ty: KVType<&'static String>) -> Self {
+        Self {
+            k: (
+                K_NAME.with_borrow(Clone::clone),
+                K_WIDTH.get(),
+                K_TREE.with_borrow(Clone::clone).unwrap(),
+            ),
+            v: (
+                V_NAME.with_borrow(Clone::clone),
+                V_WIDTH.get(),
+                V_TREE.with_borrow(Clone::clone).unwrap(),
+            ),
+            ty,
+        }
+    }
+
+    pub fn enter(&self) -> KVType<&'static String> {
+        K_NAME.set(self.k.0.clone());
+        K_WIDTH.set(self.k.1);
+        K_TREE.set(Some(self.k.2.clone()));
+
+        V_NAME.set(self.v.0.clone());
+        V_WIDTH.set(self.v.1);
+        V_TREE.set(Some(self.v.2.clone()));
+
+        self.ty
+    }
+
+    /// Type names the table is stored with
+    pub fn names(&self) -> [&str; 2] {
+        [self.k.0.name(), self.v.0.name()]
+    }
+
+    /// Entries of unknown key types can still be listed, but not looked up
+    pub fn can_order(&self) -> bool {
+        parser::can_order(&self.k.2)
+    }
+}
+
+/// Commit, or roll back a dry run once its changes are reported
+pub fn commit(w: WriteTransaction, dry_run: bool) -> Result<()> {
+    match dry_run {
+        true => w.abort()?,
+        false => w.commit()?,
+    }
+    Ok(())
+}
+
 #[derive(Default)]
 pub struct Data {
     pub stats: BTreeMap<String, BTreeMap<String, u64>>,
     pub list: BTreeMap<String, Vec<String>>,
-    pub out: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+    pub printer: print::Printer,
     pub types: BTreeMap<String, KVType<&'static String>>,
+    /// Changes made by a dry run
+    pub changes: Option<changes::Changes>,
 }
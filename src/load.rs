@@ -0,0 +1,248 @@
+use eyre::{Result, WrapErr, bail, eyre};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cli::{InputFormat, LoadArgs},
+    parser::TypeDesc,
+    transcode::{K_TREE, V_TREE, check_key, check_value},
+};
+
+/// A single NDJSON record, the same as written by `--ndjson`
+#[derive(Deserialize)]
+struct Entry {
+    key: Value,
+    value: Value,
+}
+
+type Entries = Box<dyn Iterator<Item = Result<(Value, Value)>>>;
+
+/// Read key-value pairs to insert, already checked against the table types
+pub fn entries(load: &LoadArgs) -> Result<Entries> {
+    let input: Box<dyn BufRead> = match load.input.as_deref() {
+        None | Some("-") => Box::new(std::io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(
+            std::fs::File::open(path).wrap_err_with(|| format!("Can't open {path:?}"))?,
+        )),
+    };
+
+    let format = load.input_format.unwrap_or_else(|| {
+        match load.input.as_deref().and_then(|p| p.rsplit_once('.')) {
+            Some((_, "csv")) => InputFormat::Csv,
+            Some((_, "tsv")) => InputFormat::Tsv,
+            _ => InputFormat::Ndjson,
+        }
+    });
+
+    Ok(match format {
+        InputFormat::Ndjson => {
+            if !load.columns.is_empty() {
+                bail!("Column mapping is only used with CSV and TSV");
+            }
+            Box::new(ndjson(input))
+        }
+        InputFormat::Csv => csv(input, b',', &load.columns)?,
+        InputFormat::Tsv => csv(input, b'\t', &load.columns)?,
+    })
+}
+
+fn ndjson(input: Box<dyn BufRead>) -> impl Iterator<Item = Result<(Value, Value)>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(|(i, line)| {
+            let entry = |line: String| -> Result<_> {
+                let entry = serde_json::from_str::<Entry>(&line)?;
+                checked(entry.key, entry.value)
+            };
+            entry(line?).wrap_err_with(|| format!("Error loading line {}", i + 1))
+        })
+}
+
+fn checked(key: Value, value: Value) -> Result<(Value, Value)> {
+    Ok((check_key(key)?, check_value(value)?))
+}
+
+/// Where a column goes, e.g. `value.name` is the `name` field of the value
+struct Column {
+    is_key: bool,
+    path: Vec<String>,
+    ty: TypeDesc,
+}
+
+/// Rows are mapped to entries by the header, whose names can be replaced with `--column`
+fn csv(input: Box<dyn BufRead>, delimiter: u8, mapping: &[String]) -> Result<Entries> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(input);
+
+    let mut mapping = mapping
+        .iter()
+        .map(|m| {
+            m.split_once('=')
+                .ok_or_else(|| eyre!("Use = to map a column to its target, e.g. id=key"))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let k_tree = K_TREE.with_borrow(|t| t.clone().unwrap());
+    let v_tree = V_TREE.with_borrow(|t| t.clone().unwrap());
+
+    let mut columns = Vec::new();
+    for name in reader.headers()? {
+        let mapped = mapping.remove(name);
+        let target = mapped.unwrap_or(name);
+        let mut path = target.split('.').map(str::to_string);
+        let (is_key, tree) = match path.next().as_deref() {
+            Some("key") => (true, &k_tree),
+            Some("value") => (false, &v_tree),
+            // Unmapped columns that aren't named after the key or value are skipped
+            _ if mapped.is_none() => {
+                columns.push(None);
+                continue;
+            }
+            _ => bail!("Target of column {name:?} must start with key or value, got {target:?}"),
+        };
+        let path: Vec<_> = path.collect();
+        let ty = field(tree, &path)
+            .wrap_err_with(|| format!("No {target:?} to map column {name:?} to"))?
+            .clone();
+        columns.push(Some(Column { is_key, path, ty }));
+    }
+    if let Some(name) = mapping.into_keys().next() {
+        bail!("No column {name:?} in the header");
+    }
+    for is_key in [true, false] {
+        if !columns.iter().flatten().any(|c| c.is_key == is_key) {
+            let name = if is_key { "key" } else { "value" };
+            bail!("No column is mapped to the {name}, use e.g. --column id={name}");
+        }
+    }
+
+    Ok(Box::new(reader.into_records().map(move |record| {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        let mut key = Value::Null;
+        let mut value = Value::Null;
+        for (column, cell) in columns.iter().zip(&record) {
+            let Some(Column { is_key, path, ty }) = column else {
+                continue;
+            };
+            let (root, tree) = match is_key {
+                true => (&mut key, &k_tree),
+                false => (&mut value, &v_tree),
+            };
+            let cell = cell_to_val(ty, cell).wrap_err_with(|| {
+                let target = if *is_key { "key" } else { "value" };
+                format!(
+                    "Error loading line {line}: invalid {target}.{}",
+                    path.join(".")
+                )
+            })?;
+            set_field(root, tree, path, cell);
+        }
+        checked(key, value).wrap_err_with(|| format!("Error loading line {line}"))
+    })))
+}
+
+/// Type of a struct field or tuple element at the path
+fn field<'a>(ty: &'a TypeDesc, path: &[String]) -> Result<&'a TypeDesc> {
+    let Some((seg, rest)) = path.split_first() else {
+        return Ok(ty);
+    };
+    let ty = match ty {
+        TypeDesc::Struct { fields, .. } => {
+            fields.iter().find(|(name, _)| name == seg).map(|f| &f.1)
+        }
+        TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+            seg.parse().ok().and_then(|i: usize| fields.get(i))
+        }
+        TypeDesc::Array { ty, n, .. } => seg.parse().ok().filter(|i: &usize| i < n).map(|_| &**ty),
+        _ => None,
+    };
+    field(ty.ok_or_else(|| eyre!("Unknown field {seg:?}"))?, rest)
+}
+
+/// Place a cell into the key or value, creating the objects and arrays on the way
+fn set_field(root: &mut Value, ty: &TypeDesc, path: &[String], val: Value) {
+    let Some((seg, rest)) = path.split_first() else {
+        *root = val;
+        return;
+    };
+    match ty {
+        TypeDesc::Struct { fields, .. } => {
+            if !root.is_object() {
+                *root = Value::Object(Default::default());
+            }
+            let ty = &fields.iter().find(|(name, _)| name == seg).unwrap().1;
+            let map = root.as_object_mut().unwrap();
+            set_field(map.entry(seg).or_insert(Value::Null), ty, rest, val);
+        }
+        // Newtype structs are encoded and shown as the field itself
+        TypeDesc::TupleStruct { fields, .. } if fields.len() == 1 => {
+            set_field(root, &fields[0], rest, val)
+        }
+        _ => {
+            let i: usize = seg.parse().unwrap();
+            let (n, ty) = match ty {
+                TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+                    (fields.len(), &fields[i])
+                }
+                TypeDesc::Array { ty, n, .. } => (*n, &**ty),
+                _ => unreachable!(),
+            };
+            if !root.is_array() {
+                *root = Value::Array(vec![Value::Null; n]);
+            }
+            set_field(&mut root.as_array_mut().unwrap()[i], ty, rest, val);
+        }
+    }
+}
+
+/// Strings are taken as is, other types are parsed as JSON, and empty cells of options are null
+fn cell_to_val(ty: &TypeDesc, cell: &str) -> Result<Value> {
+    Ok(match ty {
+        TypeDesc::Option { .. } if cell.is_empty() => Value::Null,
+        TypeDesc::Option { ty, .. } => cell_to_val(ty, cell)?,
+        TypeDesc::String | TypeDesc::Char | TypeDesc::Raw { .. } => Value::String(cell.into()),
+        _ => serde_json::from_str(cell)?,
+    })
+}
+
+/// Count of loaded entries, updated in place when stderr is a terminal
+pub struct Progress {
+    table: String,
+    last: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            last: std::io::stderr().is_terminal().then(Instant::now),
+        }
+    }
+
+    pub fn update(&mut self, count: usize) {
+        if let Some(last) = &mut self.last
+            && last.elapsed() > Duration::from_millis(100)
+        {
+            *last = Instant::now();
+            eprint!("\rLoading {:?}: {count} entries", self.table);
+            std::io::stderr().flush().ok();
+        }
+    }
+
+    pub fn finish(self, count: usize, dry_run: bool) {
+        if self.last.is_some() {
+            eprint!("\r\x1b[K");
+        }
+        let verb = if dry_run { "Would load" } else { "Loaded" };
+        eprintln!("{verb} {count} entries into {:?}", self.table);
+    }
+}
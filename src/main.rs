@@ -1,10 +1,38 @@
-use clap::Parser;
 use eyre::Result;
 
-use redb_cli::{DB, Data, cli::CliArgs, print, process, process_multimap};
+use redb_cli::{
+    DB, Data, batch,
+    changes::Changes,
+    cli::{Cli, CliArgs, Savepoint},
+    copy, diff, dump,
+    print::{self, Printer},
+    process, process_multimap, shell, tui,
+};
 
 fn main() -> Result<()> {
-    let args = CliArgs::parse();
+    let args = Cli::parse_args().into_args();
+
+    match run(args) {
+        // Output is streamed, so the reader may go away early, e.g. `redb-cli db table | head`
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+fn run(args: CliArgs) -> Result<()> {
+    if let Some([left, right]) = &args.diff {
+        return diff::run(&args, left, right);
+    }
+
+    if let Some(copy) = &args.copy {
+        return copy::run(&args, copy);
+    }
 
     let mut db = if args.ro {
         DB::R(redb::ReadOnlyDatabase::open(&args.file)?)
@@ -14,6 +42,32 @@ fn main() -> Result<()> {
         DB::RW(redb::Database::open(&args.file)?)
     };
 
+    if let Some(restore) = &args.restore {
+        return dump::restore(&db, restore.input.as_deref());
+    }
+
+    if let Some(batch) = &args.batch {
+        return batch::run(&args, &db, batch);
+    }
+
+    if args.shell {
+        return shell::run(&args, db);
+    }
+
+    if args.tui {
+        return tui::run(&args, db);
+    }
+
+    if let Some(savepoint) = args.savepoint {
+        match savepoint {
+            Savepoint::Create => println!("{}", db.persistent_savepoint()?),
+            Savepoint::List => print::print_savepoints(&args, &db.list_persistent_savepoints()?)?,
+            Savepoint::Restore(id) => db.restore_savepoint(id)?,
+            Savepoint::Delete(id) => db.delete_persistent_savepoint(id)?,
+        }
+        return Ok(());
+    }
+
     if args.check {
         db.check_integrity()?;
         return Ok(());
@@ -24,7 +78,11 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut data = Data::default();
+    let mut data = Data {
+        printer: Printer::new(&args)?,
+        changes: args.dry_run.then(|| Changes::new(args.format)),
+        ..Default::default()
+    };
 
     if args.multimap {
         process_multimap::process_multimap(&args, &db, &mut data)?;
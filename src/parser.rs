@@ -1,11 +1,14 @@
-use eyre::{ContextCompat, Result, bail, eyre};
+use eyre::{Result, bail, eyre};
 use pest::{Parser, iterators::Pair};
 use pest_derive::Parser;
 use serde_json::{Number, Value};
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+};
 use thiserror::Error;
 
-use crate::utils::OrElseRes;
+use crate::utils::{from_hex, to_hex};
 
 pub type TakeResult<T> = std::result::Result<T, TakeError>;
 
@@ -15,6 +18,55 @@ pub enum TakeError {
     OffBuffer,
 }
 
+pub type CodecResult<T> = std::result::Result<T, CodecError>;
+
+/// Value that doesn't match its type, found while encoding or decoding an entry
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct CodecError {
+    /// Where in the value the error is, e.g. `Log.tags[3]`
+    pub path: String,
+    /// Position in the encoded data, only known when decoding
+    pub offset: Option<usize>,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl CodecError {
+    pub fn new(expected: impl Display, actual: impl Display) -> Self {
+        Self {
+            path: String::new(),
+            offset: None,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+
+    /// Prepend the segment of the path leading to the erroneous part
+    fn within(mut self, segment: impl Into<String>) -> Self {
+        self.path.insert_str(0, &segment.into());
+        self
+    }
+
+    /// Point the offset to the start of data that was already consumed
+    fn back(mut self, n: usize) -> Self {
+        self.offset = self.offset.map(|x| x - n);
+        self
+    }
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "expected {}, found {}", self.expected, self.actual)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset}")?;
+        }
+        Ok(())
+    }
+}
+
 #[track_caller]
 pub fn take_n<'a>(data: &mut &'a [u8], len: usize) -> TakeResult<&'a [u8]> {
     let res;
@@ -69,503 +121,953 @@ pub fn put_varint(len: usize, buf: &mut Vec<u8>) {
 #[grammar = "src/grammar.pest"]
 pub struct Grammar;
 
-pub fn parse_from_tree(p: Pair<'_, Rule>, mut data: &[u8]) -> Result<serde_json::Value> {
-    parse(p, &mut data)
+/// Type tree compiled from the type name, with fixed widths computed up front
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDesc {
+    Bool,
+    Char,
+    Int {
+        bits: usize,
+        signed: bool,
+    },
+    Float {
+        bits: usize,
+    },
+    String,
+    Slice,
+    Option {
+        ty: Box<TypeDesc>,
+        width: Option<usize>,
+    },
+    Vec(Box<TypeDesc>),
+    Array {
+        ty: Box<TypeDesc>,
+        n: usize,
+        width: Option<usize>,
+    },
+    Tuple {
+        fields: Vec<TypeDesc>,
+        width: Option<usize>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, TypeDesc)>,
+        width: Option<usize>,
+    },
+    /// Struct with unnamed fields, also covers newtype `Name(T)` and unit `Name` structs
+    TupleStruct {
+        name: String,
+        fields: Vec<TypeDesc>,
+        width: Option<usize>,
+    },
+    /// Variants in declaration order, with the fields of tuple and struct variants
+    Enum {
+        name: String,
+        variants: Vec<(String, Option<TypeDesc>)>,
+        width: Option<usize>,
+    },
+    /// Bytes shown as a hex string, used for unknown types and `--raw`. Ordered by the
+    /// underlying type if it's known
+    Raw {
+        ty: Option<Box<TypeDesc>>,
+        width: Option<usize>,
+    },
+}
+
+impl TypeDesc {
+    pub fn new(p: Pair<'_, Rule>) -> Result<Self> {
+        let s = p.as_str();
+
+        let err_type = || Err(eyre!("Unknown type {s:?}"));
+
+        Ok(match p.as_rule() {
+            Rule::Bool => Self::Bool,
+            Rule::Char => Self::Char,
+            Rule::Int => {
+                let bits = s[1..].parse()?;
+                if !matches!(bits, 8 | 16 | 32 | 64 | 128) {
+                    return err_type();
+                }
+                Self::Int {
+                    bits,
+                    signed: s.starts_with('i'),
+                }
+            }
+            Rule::Float => match s {
+                "f32" => Self::Float { bits: 32 },
+                "f64" => Self::Float { bits: 64 },
+                _ => return err_type(),
+            },
+            Rule::String => Self::String,
+            Rule::Slice => Self::Slice,
+            Rule::Option => {
+                let ty = Self::new(p.into_inner().next().unwrap())?;
+                Self::Option {
+                    width: ty.width().map(|x| 1 + x),
+                    ty: Box::new(ty),
+                }
+            }
+            Rule::Vec => Self::Vec(Box::new(Self::new(p.into_inner().next().unwrap())?)),
+            Rule::Array => {
+                let mut iter = p.into_inner();
+                let ty = Self::new(iter.next().unwrap())?;
+                let n = iter.next().unwrap();
+                assert_eq!(n.as_rule(), Rule::Num);
+                let n: usize = n.as_str().parse()?;
+                Self::Array {
+                    width: ty.width().map(|x| x * n),
+                    ty: Box::new(ty),
+                    n,
+                }
+            }
+            Rule::Tuple => {
+                let fields = p.into_inner().map(Self::new).collect::<Result<Vec<_>>>()?;
+                Self::Tuple {
+                    width: fields.iter().map(Self::width).sum(),
+                    fields,
+                }
+            }
+            Rule::Struct => {
+                let mut iter = p.into_inner();
+                // Span of the name includes whitespace before the fields
+                let name = iter.next().unwrap().as_str().trim_end().to_string();
+                let mut fields = Vec::new();
+                while let Some(field) = iter.next() {
+                    let ty = Self::new(iter.next().unwrap())?;
+                    fields.push((field.as_str().to_string(), ty));
+                }
+                Self::Struct {
+                    width: fields.iter().map(|(_, ty)| ty.width()).sum(),
+                    name,
+                    fields,
+                }
+            }
+            Rule::TupleStruct | Rule::UnitStruct => {
+                let mut iter = p.into_inner();
+                let name = iter.next().unwrap().as_str().trim_end().to_string();
+                let fields = iter.map(Self::new).collect::<Result<Vec<_>>>()?;
+                Self::TupleStruct {
+                    width: fields.iter().map(Self::width).sum(),
+                    name,
+                    fields,
+                }
+            }
+            Rule::Enum => {
+                // Skip `enum` keyword
+                let mut iter = p.into_inner().skip(1);
+                let name = iter.next().unwrap().as_str().to_string();
+                let variants = iter.map(Self::variant).collect::<Result<Vec<_>>>()?;
+                if variants.len() > 256 {
+                    bail!("Enum {name:?} has more than 256 variants");
+                }
+                // Fixed width variants are padded to the largest one, like `None` of `Option`
+                let width = variants
+                    .iter()
+                    .map(|(_, ty)| ty.as_ref().map_or(Some(0), Self::width))
+                    .try_fold(0, |max, width| Some(Ord::max(max, width?)));
+                Self::Enum {
+                    width: width.map(|x| 1 + x),
+                    name,
+                    variants,
+                }
+            }
+            _ => return err_type(),
+        })
+    }
+
+    fn variant(p: Pair<'_, Rule>) -> Result<(String, Option<Self>)> {
+        let mut iter = p.into_inner();
+        let first = iter.next().unwrap();
+        if first.as_rule() == Rule::Struct {
+            let ty = Self::new(first)?;
+            let Self::Struct { name, .. } = &ty else {
+                unreachable!()
+            };
+            return Ok((name.clone(), Some(ty)));
+        }
+
+        let ty = match iter.next().map(Self::new).transpose()? {
+            // Newtype variants hold the field itself
+            Some(Self::Tuple { mut fields, .. }) if fields.len() == 1 => fields.pop(),
+            ty => ty,
+        };
+        Ok((first.as_str().to_string(), ty))
+    }
+
+    /// Fixed width of the encoded value, [`None`] if it's variable
+    pub fn width(&self) -> Option<usize> {
+        match self {
+            Self::Bool => Some(1),
+            Self::Char => <char as redb::Value>::fixed_width(),
+            Self::Int { bits, .. } | Self::Float { bits } => Some(bits / 8),
+            Self::String | Self::Slice | Self::Vec(_) => None,
+            Self::Option { width, .. }
+            | Self::Array { width, .. }
+            | Self::Tuple { width, .. }
+            | Self::Struct { width, .. }
+            | Self::TupleStruct { width, .. }
+            | Self::Enum { width, .. }
+            | Self::Raw { width, .. } => *width,
+        }
+    }
+
+    /// Show values as raw bytes, keeping the ordering of the type
+    pub fn into_raw(self) -> Self {
+        match self {
+            Self::Raw { .. } => self,
+            ty => Self::Raw {
+                width: ty.width(),
+                ty: Some(Box::new(ty)),
+            },
+        }
+    }
+
+    /// Variable width fields of tuples and structs, except the last one, are prefixed by length
+    fn prefixed<'a>(fields: impl ExactSizeIterator<Item = &'a TypeDesc>) -> Vec<bool> {
+        let last = fields.len().saturating_sub(1);
+        fields
+            .enumerate()
+            .map(|(i, ty)| ty.width().is_none() && i != last)
+            .collect()
+    }
+}
+
+/// Rust-like name of the type, structs and enums are named without their fields
+impl Display for TypeDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool => write!(f, "bool"),
+            Self::Char => write!(f, "char"),
+            Self::Int { bits, signed } => write!(f, "{}{bits}", if *signed { 'i' } else { 'u' }),
+            Self::Float { bits } => write!(f, "f{bits}"),
+            Self::String => write!(f, "String"),
+            Self::Slice => write!(f, "&[u8]"),
+            Self::Option { ty, .. } => write!(f, "Option<{ty}>"),
+            Self::Vec(ty) => write!(f, "Vec<{ty}>"),
+            Self::Array { ty, n, .. } => write!(f, "[{ty}; {n}]"),
+            Self::Tuple { fields, .. } => {
+                write!(f, "(")?;
+                for (i, ty) in fields.iter().enumerate() {
+                    match i {
+                        0 => write!(f, "{ty}")?,
+                        _ => write!(f, ", {ty}")?,
+                    }
+                }
+                match fields.len() {
+                    1 => write!(f, ",)"),
+                    _ => write!(f, ")"),
+                }
+            }
+            Self::Struct { name, .. } | Self::TupleStruct { name, .. } => write!(f, "{name}"),
+            Self::Enum { name, .. } => write!(f, "enum {name}"),
+            Self::Raw { .. } => write!(f, "hex string"),
+        }
+    }
+}
+
+pub fn parse_from_tree(ty: &TypeDesc, data: &[u8]) -> CodecResult<serde_json::Value> {
+    let mut rest = data;
+    let val = parse(ty, &mut rest)?;
+    if !rest.is_empty() {
+        return Err(CodecError {
+            path: root_path(ty),
+            offset: Some(data.len() - rest.len()),
+            expected: "end of data".into(),
+            actual: format!("{} trailing bytes", rest.len()),
+        });
+    }
+    Ok(val)
 }
 
-pub fn parse_tree(ty: &str) -> Result<Pair<'_, Rule>> {
-    Ok(Grammar::parse(Rule::FullType, ty)?.next().unwrap())
+pub fn parse_tree(ty: &str) -> Result<TypeDesc> {
+    TypeDesc::new(Grammar::parse(Rule::FullType, ty)?.next().unwrap())
 }
 
 #[allow(unused)]
 pub fn parse_type(ty: &str, mut data: &[u8]) -> Result<serde_json::Value> {
-    parse(parse_tree(ty)?, &mut data)
+    Ok(parse(&parse_tree(ty)?, &mut data)?)
 }
 
 #[allow(unused)]
 pub fn encode_type(ty: &str, val: &serde_json::Value, buf: &mut Vec<u8>) -> Result<()> {
-    encode(parse_tree(ty)?, val, buf)
-}
-
-pub fn encode(p: Pair<'_, Rule>, val: &serde_json::Value, buf: &mut Vec<u8>) -> Result<()> {
-    let s = p.as_str();
-
-    let err_type = || Err(eyre!("Unknown type {s:?}"));
-    let err_val = || eyre!("Invalid value in {s:?}");
-    let err_var_num = || Err(err_val());
-    let err_var_u64 = || Err(err_val());
-    let err_var_i128 = || Err(err_val());
-    let err_var_u128 = || Err(err_val());
-    let err_var_f64 = || Err(err_val());
-    let err_var_arr = || Err(err_val());
-    let err_var_str = || Err(err_val());
-    let err_var_u8 = |err| eyre!("Error {s:?}: {err}");
-    let err_num = |err| eyre!("Error {s:?}: {err}");
-
-    match p.as_rule() {
-        Rule::Bool => match val.as_bool().unwrap() {
+    Ok(encode(&parse_tree(ty)?, val, buf)?)
+}
+
+/// Name of the outermost type that starts error paths, e.g. `Log` in `Log.tags[3]`
+fn root_path(ty: &TypeDesc) -> String {
+    match ty {
+        TypeDesc::Struct { name, .. }
+        | TypeDesc::TupleStruct { name, .. }
+        | TypeDesc::Enum { name, .. } => name.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Short form of a value for error messages
+fn describe(val: &Value) -> String {
+    let mut s = val.to_string();
+    if s.len() > 40 {
+        let end = (0..=37).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0);
+        s.truncate(end);
+        s.push_str("...");
+    }
+    s
+}
+
+pub fn encode(ty: &TypeDesc, val: &serde_json::Value, buf: &mut Vec<u8>) -> CodecResult<()> {
+    encode_value(ty, val, buf).map_err(|err| err.within(root_path(ty)))
+}
+
+fn encode_value(ty: &TypeDesc, val: &Value, buf: &mut Vec<u8>) -> CodecResult<()> {
+    let err_val = || CodecError::new(ty, describe(val));
+
+    // Entries that failed to decode are written back as they were read, see [`raw_value`]
+    if let Some(hex) = as_raw(val) {
+        let bytes = from_hex(hex).map_err(|_| CodecError::new("hex string", describe(val)))?;
+        if let Some(width) = ty.width()
+            && bytes.len() != width
+        {
+            return Err(CodecError::new(
+                format!("{width} raw bytes"),
+                format!("{} bytes", bytes.len()),
+            ));
+        }
+        buf.extend_from_slice(&bytes);
+        return Ok(());
+    }
+
+    match ty {
+        TypeDesc::Bool => match val.as_bool().ok_or_else(err_val)? {
             false => buf.push(0),
             true => buf.push(1),
         },
-        Rule::Char => {
-            let val = val.as_str().unwrap();
-            if val.chars().count() != 1 {
-                bail!("Char must be a single codepoint");
-            }
-            let val = <char as redb::Value>::as_bytes(&val.chars().next().unwrap());
-            let val = val.as_ref();
-            buf.extend_from_slice(val);
-        }
-        Rule::Int => {
-            if let Some(bits) = s.strip_prefix("u") {
-                let val = val
-                    .as_number()
-                    .or_else_res(err_var_num)?
-                    .as_u128()
-                    .or_else_res(err_var_u128)?;
-                match bits {
-                    "8" => buf.extend(u8::to_le_bytes(val.try_into().unwrap())),
-                    "16" => buf.extend(u16::to_le_bytes(val.try_into().unwrap())),
-                    "32" => buf.extend(u32::to_le_bytes(val.try_into().unwrap())),
-                    "64" => buf.extend(u64::to_le_bytes(val.try_into().unwrap())),
-                    "128" => buf.extend(u128::to_le_bytes(val.try_into().unwrap())),
-                    _ => return err_type(),
-                };
-            } else if let Some(bits) = s.strip_prefix("i") {
-                let val = val
-                    .as_number()
-                    .or_else_res(err_var_num)?
-                    .as_i128()
-                    .or_else_res(err_var_i128)?;
-                match bits {
-                    "8" => buf.extend(i8::to_le_bytes(val.try_into().unwrap())),
-                    "16" => buf.extend(i16::to_le_bytes(val.try_into().unwrap())),
-                    "32" => buf.extend(i32::to_le_bytes(val.try_into().unwrap())),
-                    "64" => buf.extend(i64::to_le_bytes(val.try_into().unwrap())),
-                    "128" => buf.extend(i128::to_le_bytes(val.try_into().unwrap())),
-                    _ => return err_type(),
-                };
-            } else {
-                unreachable!()
+        TypeDesc::Char => {
+            let mut chars = val.as_str().ok_or_else(err_val)?.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(err_val());
+            };
+            buf.extend_from_slice(&u32::from(c).to_le_bytes()[..3]);
+        }
+        TypeDesc::Int {
+            bits,
+            signed: false,
+        } => {
+            let val = val
+                .as_number()
+                .and_then(Number::as_u128)
+                .ok_or_else(err_val)?;
+            let err_range = |_| err_val();
+            match bits {
+                8 => buf.extend(u8::try_from(val).map_err(err_range)?.to_le_bytes()),
+                16 => buf.extend(u16::try_from(val).map_err(err_range)?.to_le_bytes()),
+                32 => buf.extend(u32::try_from(val).map_err(err_range)?.to_le_bytes()),
+                64 => buf.extend(u64::try_from(val).map_err(err_range)?.to_le_bytes()),
+                _ => buf.extend(val.to_le_bytes()),
             }
         }
-        Rule::Float => {
+        TypeDesc::Int { bits, signed: true } => {
             let val = val
                 .as_number()
-                .or_else_res(err_var_num)?
-                .as_f64()
-                .or_else_res(err_var_f64)?;
-            match s {
-                "f32" => buf.extend(f32::to_le_bytes(val as f32)),
-                "f64" => buf.extend(f64::to_le_bytes(val)),
-                _ => return err_type(),
+                .and_then(Number::as_i128)
+                .ok_or_else(err_val)?;
+            let err_range = |_| err_val();
+            match bits {
+                8 => buf.extend(i8::try_from(val).map_err(err_range)?.to_le_bytes()),
+                16 => buf.extend(i16::try_from(val).map_err(err_range)?.to_le_bytes()),
+                32 => buf.extend(i32::try_from(val).map_err(err_range)?.to_le_bytes()),
+                64 => buf.extend(i64::try_from(val).map_err(err_range)?.to_le_bytes()),
+                _ => buf.extend(val.to_le_bytes()),
             }
         }
-        Rule::String => {
-            let val = val.as_str().or_else_res(err_var_str)?;
+        TypeDesc::Float { bits } => {
+            let val = match val {
+                Value::String(s) => string_to_float(s),
+                _ => val.as_number().and_then(Number::as_f64),
+            };
+            let val = val.ok_or_else(err_val)?;
+            match bits {
+                // Don't silently turn large numbers into infinity
+                32 if val.is_finite() && !(val as f32).is_finite() => return Err(err_val()),
+                32 => buf.extend(f32::to_le_bytes(val as f32)),
+                _ => buf.extend(f64::to_le_bytes(val)),
+            }
+        }
+        TypeDesc::String => {
+            let val = val.as_str().ok_or_else(err_val)?;
             buf.extend_from_slice(val.as_bytes());
         }
-        Rule::Slice => {
-            let val = val.as_array().or_else_res(err_var_arr)?;
-            for v in val {
-                let b = v
-                    .as_number()
-                    .or_else_res(err_var_num)?
-                    .as_u64()
-                    .or_else_res(err_var_u64)?;
-                buf.push(b.try_into().map_err(err_var_u8)?);
+        TypeDesc::Slice => {
+            let val = val.as_array().ok_or_else(err_val)?;
+            for (i, v) in val.iter().enumerate() {
+                let b = v.as_u64().and_then(|b| u8::try_from(b).ok());
+                let b =
+                    b.ok_or_else(|| CodecError::new("u8", describe(v)).within(format!("[{i}]")))?;
+                buf.push(b);
             }
         }
-        Rule::Array => {
-            let mut iter = p.into_inner();
-            let ty = iter.next().unwrap();
-            let n = iter.next().unwrap();
-            assert_eq!(n.as_rule(), Rule::Num);
-            let n: usize = n.as_str().parse().map_err(err_num)?;
-
-            let val = val.as_array().or_else_res(err_var_arr)?;
-            if val.len() != n {
-                bail!("Array length doesn't match {s:?}");
+        TypeDesc::Array { ty, n, .. } => {
+            let val = val.as_array().ok_or_else(err_val)?;
+            if val.len() != *n {
+                return Err(CodecError::new(
+                    format!("{n} elements"),
+                    format!("{} elements", val.len()),
+                ));
             }
 
-            let ty_size = parse_size(ty.clone())?;
-
-            let mut vec = Vec::new();
-            for v in val {
-                vec.clear();
-                encode(ty.clone(), v, &mut vec)?;
-                if ty_size.is_none() {
-                    buf.extend_from_slice(u32::to_le_bytes(vec.len() as u32).as_slice());
+            let start = buf.len();
+            if ty.width().is_none() {
+                // Variable width elements are preceded by a table of their end offsets
+                buf.resize(start + 4 * n, 0);
+            }
+            for (i, v) in val.iter().enumerate() {
+                encode_value(ty, v, buf).map_err(|err| err.within(format!("[{i}]")))?;
+                if ty.width().is_none() {
+                    let end = u32::to_le_bytes((buf.len() - start) as u32);
+                    buf[start + 4 * i..start + 4 * (i + 1)].copy_from_slice(&end);
                 }
-                buf.extend_from_slice(&vec);
             }
         }
-        Rule::Option => {
-            let ty = p.into_inner().next().unwrap();
+        TypeDesc::Option { ty, .. } => {
             if val.is_null() {
                 buf.push(0);
-                if let Some(s) = parse_size(ty)? {
+                if let Some(s) = ty.width() {
                     buf.extend(std::iter::repeat_n(0, s));
                 }
             } else {
                 buf.push(1);
-                encode(ty, val, buf)?;
+                encode_value(ty, val, buf)?;
             }
         }
-        Rule::Vec => {
-            let ty = p.into_inner().next().unwrap();
-            let val = val.as_array().or_else_res(err_var_arr)?;
+        TypeDesc::Vec(ty) => {
+            let val = val.as_array().ok_or_else(err_val)?;
 
             put_varint(val.len(), buf);
 
-            let ty_size = parse_size(ty.clone())?;
-
             let mut vec = Vec::new();
-            for i in 0..val.len() {
-                vec.clear();
-                encode(ty.clone(), &val[i], &mut vec)?;
-
-                if ty_size.is_none() {
+            for (i, v) in val.iter().enumerate() {
+                let within = |err: CodecError| err.within(format!("[{i}]"));
+                if ty.width().is_some() {
+                    encode_value(ty, v, buf).map_err(within)?;
+                } else {
+                    vec.clear();
+                    encode_value(ty, v, &mut vec).map_err(within)?;
                     put_varint(vec.len(), buf);
+                    buf.extend_from_slice(&vec);
                 }
-                buf.extend_from_slice(&vec);
             }
         }
-        Rule::Tuple => {
-            let val = if val.is_null() {
-                &Vec::new()
-            } else {
-                val.as_array().or_else_res(err_var_arr)?
+        // Newtype structs are encoded and shown as the field itself
+        TypeDesc::TupleStruct { fields, .. } if fields.len() == 1 => {
+            encode_value(&fields[0], val, buf).map_err(|err| err.within(".0"))?
+        }
+        TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+            let val = match val {
+                Value::Null => &Vec::new(),
+                _ => val.as_array().ok_or_else(err_val)?,
             };
-            let iter = p.into_inner();
-            assert_eq!(val.len(), iter.len());
+            if val.len() != fields.len() {
+                return Err(CodecError::new(
+                    format!("{} elements", fields.len()),
+                    format!("{} elements", val.len()),
+                ));
+            }
 
-            let mut iter = Iterator::zip(iter, val).peekable();
+            let prefixed = TypeDesc::prefixed(fields.iter());
 
             let mut vec = Vec::new();
-            while let Some((ty, val)) = iter.next() {
+            for (i, ((ty, val), prefixed)) in fields.iter().zip(val).zip(prefixed).enumerate() {
                 let last_len = vec.len();
-                encode(ty.clone(), val, &mut vec)?;
+                encode_value(ty, val, &mut vec).map_err(|err| err.within(format!(".{i}")))?;
 
-                let ty_size = parse_size(ty.clone())?;
-                if ty_size.is_none() && iter.peek().is_some() {
+                if prefixed {
                     put_varint(vec.len() - last_len, buf);
                 }
             }
             buf.extend_from_slice(&vec);
         }
-        Rule::Struct => {
-            let mut iter = p.into_inner();
-            let struct_name = iter.next().unwrap().as_str();
+        TypeDesc::Struct { name, fields, .. } => {
+            let mut val = val.as_object().ok_or_else(err_val)?.clone();
 
-            let mut val = val.as_object().unwrap().clone();
+            let prefixed = TypeDesc::prefixed(fields.iter().map(|(_, ty)| ty));
 
             let mut vec = Vec::new();
-            while iter.peek().is_some() {
-                let name = iter.next().unwrap().as_str();
-                let ty = iter.next().unwrap();
-
-                let val = val
-                    .remove(name)
-                    .or_else(|| matches!(ty.as_rule(), Rule::Option).then_some(Value::Null))
-                    .with_context(|| eyre!("Expected field {name:?} in struct {struct_name:?}"))?;
+            for ((field, ty), prefixed) in fields.iter().zip(prefixed) {
+                let within = |err: CodecError| err.within(format!(".{field}"));
+                let val = match val.remove(field) {
+                    Some(val) => val,
+                    None if matches!(ty, TypeDesc::Option { .. }) => Value::Null,
+                    None => return Err(within(CodecError::new(ty, "missing field"))),
+                };
 
                 let last_len = vec.len();
-                encode(ty.clone(), &val, &mut vec)?;
+                encode_value(ty, &val, &mut vec).map_err(within)?;
 
-                let ty_size = parse_size(ty.clone())?;
-                if ty_size.is_none() && iter.peek().is_some() {
+                if prefixed {
                     put_varint(vec.len() - last_len, buf);
                 }
             }
             buf.extend_from_slice(&vec);
 
             if let Some((k, _)) = val.into_iter().next() {
-                bail!("Struct {struct_name:?} has undefined field {k:?}");
+                return Err(CodecError::new(
+                    format!("a field of {name}"),
+                    format!("unknown field {k:?}"),
+                ));
+            }
+        }
+        TypeDesc::Enum {
+            name,
+            variants,
+            width,
+        } => {
+            // Externally tagged, i.e. `"Variant"` or `{"Variant": fields}`
+            let (variant, val) = match val {
+                Value::String(variant) => (variant, None),
+                Value::Object(map) if map.len() == 1 => {
+                    let (variant, val) = map.iter().next().unwrap();
+                    (variant, Some(val))
+                }
+                _ => return Err(err_val()),
+            };
+            let Some((tag, (_, ty))) = variants.iter().enumerate().find(|(_, (v, _))| v == variant)
+            else {
+                return Err(CodecError::new(
+                    format!("a variant of {name}"),
+                    format!("{variant:?}"),
+                ));
+            };
+
+            let within = |err: CodecError| err.within(format!("::{variant}"));
+            let start = buf.len();
+            buf.push(tag as u8);
+            match (ty, val) {
+                (None, None) => {}
+                (Some(ty), Some(val)) => encode_value(ty, val, buf).map_err(within)?,
+                (None, Some(val)) => {
+                    return Err(within(CodecError::new("unit variant", describe(val))));
+                }
+                (Some(ty), None) => return Err(within(CodecError::new(ty, "nothing"))),
+            }
+            if let Some(width) = width {
+                buf.resize(start + width, 0);
+            }
+        }
+        TypeDesc::Raw { width, .. } => {
+            let hex = val.as_str().ok_or_else(err_val)?;
+            let bytes = from_hex(hex).map_err(|_| err_val())?;
+            if let Some(width) = width
+                && bytes.len() != *width
+            {
+                return Err(CodecError::new(
+                    format!("{width} raw bytes"),
+                    format!("{} bytes", bytes.len()),
+                ));
             }
+            buf.extend_from_slice(&bytes);
         }
-        _ => return err_type(),
     }
 
     Ok(())
 }
 
-pub fn parse(p: Pair<'_, Rule>, data: &mut &[u8]) -> Result<serde_json::Value> {
-    use serde_json::Value;
-
-    let s = p.as_str();
+pub fn parse(ty: &TypeDesc, data: &mut &[u8]) -> CodecResult<serde_json::Value> {
+    Decoder { root: data }
+        .parse(ty, data)
+        .map_err(|err| err.within(root_path(ty)))
+}
 
-    let err_type = || Err(eyre!("Unknown type {s:?}"));
+/// Keeps the whole buffer to report offsets of errors
+struct Decoder<'a> {
+    root: &'a [u8],
+}
 
-    Ok(match p.as_rule() {
-        Rule::Bool => match take_u8(data)? {
-            0 => Value::Bool(false),
-            1 => Value::Bool(true),
-            x => bail!("Unknown bool state: {x:?}"),
-        },
-        Rule::Char => {
-            let len = <char as redb::Value>::fixed_width().unwrap();
-            Value::String(<char as redb::Value>::from_bytes(take_n(data, len)?).to_string())
+impl<'a> Decoder<'a> {
+    fn err(&self, data: &[u8], expected: impl Display, actual: impl Display) -> CodecError {
+        CodecError {
+            offset: Some(data.as_ptr().addr() - self.root.as_ptr().addr()),
+            ..CodecError::new(expected, actual)
         }
-        Rule::Int => {
-            if let Some(bits) = s.strip_prefix("u") {
+    }
+
+    fn take_n(&self, data: &mut &'a [u8], len: usize) -> CodecResult<&'a [u8]> {
+        take_n(data, len).map_err(|_| {
+            self.err(
+                data,
+                format!("{len} bytes"),
+                format!("{} bytes", data.len()),
+            )
+        })
+    }
+
+    fn take<const N: usize>(&self, data: &mut &'a [u8]) -> CodecResult<[u8; N]> {
+        Ok(self.take_n(data, N)?.try_into().unwrap())
+    }
+
+    fn take_varint(&self, data: &mut &'a [u8]) -> CodecResult<usize> {
+        let start = *data;
+        take_varint(data).map_err(|_| self.err(start, "length prefix", "end of data"))
+    }
+
+    fn parse(&self, ty: &TypeDesc, data: &mut &'a [u8]) -> CodecResult<Value> {
+        Ok(match ty {
+            TypeDesc::Bool => match self.take::<1>(data)? {
+                [0] => Value::Bool(false),
+                [1] => Value::Bool(true),
+                [x] => return Err(self.err(data, ty, format!("{x:#04x}")).back(1)),
+            },
+            TypeDesc::Char => {
+                let [a, b, c] = self.take(data)?;
+                let code = u32::from_le_bytes([a, b, c, 0]);
+                match char::from_u32(code) {
+                    Some(c) => Value::String(c.to_string()),
+                    None => return Err(self.err(data, ty, format!("{code:#x}")).back(3)),
+                }
+            }
+            TypeDesc::Int {
+                bits,
+                signed: false,
+            } => {
                 let num = match bits {
-                    "8" => u8::from_le_bytes(take(data)?) as u128,
-                    "16" => u16::from_le_bytes(take(data)?) as u128,
-                    "32" => u32::from_le_bytes(take(data)?) as u128,
-                    "64" => u64::from_le_bytes(take(data)?) as u128,
-                    "128" => u128::from_le_bytes(take(data)?),
-                    _ => return err_type(),
+                    8 => u8::from_le_bytes(self.take(data)?) as u128,
+                    16 => u16::from_le_bytes(self.take(data)?) as u128,
+                    32 => u32::from_le_bytes(self.take(data)?) as u128,
+                    64 => u64::from_le_bytes(self.take(data)?) as u128,
+                    _ => u128::from_le_bytes(self.take(data)?),
                 };
-                Value::Number(Number::from_u128(num).unwrap())
-            } else if let Some(bits) = s.strip_prefix("i") {
+                Value::Number(num.into())
+            }
+            TypeDesc::Int { bits, signed: true } => {
                 let num = match bits {
-                    "8" => i8::from_le_bytes(take(data)?) as i128,
-                    "16" => i16::from_le_bytes(take(data)?) as i128,
-                    "32" => i32::from_le_bytes(take(data)?) as i128,
-                    "64" => i64::from_le_bytes(take(data)?) as i128,
-                    "128" => i128::from_le_bytes(take(data)?),
-                    _ => return err_type(),
+                    8 => i8::from_le_bytes(self.take(data)?) as i128,
+                    16 => i16::from_le_bytes(self.take(data)?) as i128,
+                    32 => i32::from_le_bytes(self.take(data)?) as i128,
+                    64 => i64::from_le_bytes(self.take(data)?) as i128,
+                    _ => i128::from_le_bytes(self.take(data)?),
                 };
-                Value::Number(Number::from_i128(num).unwrap())
-            } else {
-                bail!("Unknown type {s:?}");
+                Value::Number(num.into())
             }
-        }
-        Rule::Float => {
-            let val = match s {
-                "f32" => f32::from_le_bytes(take(data)?) as f64,
-                "f64" => f64::from_le_bytes(take(data)?),
-                _ => return err_type(),
-            };
-            Value::Number(Number::from_f64(val).unwrap())
-        }
-        Rule::String => Value::String(String::from_utf8(take_all(data).to_vec()).unwrap()),
-        Rule::Slice => Value::Array(
-            take_all(data)
-                .iter()
-                .map(|b| Value::Number(Number::from_u128(*b as u128).unwrap()))
-                .collect(),
-        ),
-        Rule::Array => {
-            let mut iter = p.into_inner();
-            let ty = iter.next().unwrap();
-            let n = iter.next().unwrap();
-
-            assert_eq!(n.as_rule(), Rule::Num);
-            let n: usize = n.as_str().parse().unwrap();
-
-            let ty_size = parse_size(ty.clone())?;
-
-            let mut vec = Vec::with_capacity(n);
-            for _ in 0..n {
-                let len = ty_size.or_else_res(|| take_u32_len(data))?;
-                let val = parse(ty.clone(), &mut take_n(data, len)?)?;
-                vec.push(val);
+            TypeDesc::Float { bits } => {
+                let val = match bits {
+                    32 => f32::from_le_bytes(self.take(data)?) as f64,
+                    _ => f64::from_le_bytes(self.take(data)?),
+                };
+                float_to_val(val)
             }
-
-            Value::Array(vec)
-        }
-        Rule::Vec => {
-            let ty = p.into_inner().next().unwrap();
-            let ty_size = parse_size(ty.clone())?;
-            let n = take_varint(data)?;
-
-            let mut vec = Vec::with_capacity(n);
-            for _ in 0..n {
-                let len = ty_size.or_else_res(|| take_varint(data))?;
-                let val = parse(ty.clone(), &mut take_n(data, len)?)?;
-                vec.push(val);
+            TypeDesc::String => {
+                let bytes = take_all(data);
+                match std::str::from_utf8(bytes) {
+                    Ok(s) => Value::String(s.to_string()),
+                    Err(err) => {
+                        let at = &bytes[err.valid_up_to()..];
+                        return Err(self.err(at, "UTF-8 string", format!("byte {:#04x}", at[0])));
+                    }
+                }
             }
+            TypeDesc::Slice => Value::Array(
+                take_all(data)
+                    .iter()
+                    .map(|b| Value::Number((*b).into()))
+                    .collect(),
+            ),
+            TypeDesc::Array { ty, n, .. } => {
+                let mut vec = Vec::with_capacity(*n);
+                if let Some(len) = ty.width() {
+                    for i in 0..*n {
+                        let val = self.parse(ty, &mut self.take_n(data, len)?);
+                        vec.push(val.map_err(|err| err.within(format!("[{i}]")))?);
+                    }
+                } else {
+                    // Variable width elements are preceded by a table of their end offsets
+                    let mut ends = self.take_n(data, 4 * n)?;
+                    let mut start = 4 * n;
+                    for i in 0..*n {
+                        let end = u32::from_le_bytes(self.take(&mut ends)?) as usize;
+                        let Some(len) = end.checked_sub(start) else {
+                            let err = self.err(ends, format!("end offset >= {start}"), end);
+                            return Err(err.back(4).within(format!("[{i}]")));
+                        };
+                        let val = self.parse(ty, &mut self.take_n(data, len)?);
+                        vec.push(val.map_err(|err| err.within(format!("[{i}]")))?);
+                        start = end;
+                    }
+                }
 
-            Value::Array(vec)
-        }
-        Rule::Option => {
-            let ty = p.into_inner().next().unwrap();
+                Value::Array(vec)
+            }
+            TypeDesc::Vec(ty) => {
+                let n = self.take_varint(data)?;
+
+                let mut vec = Vec::new();
+                for i in 0..n {
+                    let val = match ty.width() {
+                        Some(len) => Ok(len),
+                        None => self.take_varint(data),
+                    }
+                    .and_then(|len| self.parse(ty, &mut self.take_n(data, len)?));
+                    vec.push(val.map_err(|err| err.within(format!("[{i}]")))?);
+                }
 
-            match take_u8(data)? {
-                0 => {
-                    if let Some(s) = parse_size(ty)? {
-                        take_n(data, s)?;
+                Value::Array(vec)
+            }
+            TypeDesc::Option { ty, .. } => match self.take::<1>(data)? {
+                [0] => {
+                    if let Some(s) = ty.width() {
+                        self.take_n(data, s)?;
                     }
                     Value::Null
                 }
-                1 => parse(ty, data)?,
-                n => bail!("Invalid Option discriminant {n:?}"),
-            }
-        }
-        Rule::Tuple => {
-            let mut vec_ty = Vec::new();
-            let mut iter = p.into_inner();
-            while let Some(ty) = iter.next() {
-                let mut ty_size = parse_size(ty.clone())?;
-                if iter.peek().is_some() {
-                    ty_size = Some(ty_size.or_else_res(|| take_varint(data))?);
+                [1] => self.parse(ty, data)?,
+                [x] => {
+                    let err = self.err(data, "Option tag 0 or 1", format!("{x:#04x}"));
+                    return Err(err.back(1));
+                }
+            },
+            TypeDesc::TupleStruct { fields, .. } if fields.len() == 1 => self
+                .parse(&fields[0], data)
+                .map_err(|err| err.within(".0"))?,
+            TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+                let vec = self.parse_fields(fields.iter(), data, |i| format!(".{i}"))?;
+
+                if vec.is_empty() {
+                    Value::Null
+                } else {
+                    Value::Array(vec)
                 }
-                vec_ty.push((ty, ty_size));
             }
+            TypeDesc::Struct { fields, .. } => {
+                let types = fields.iter().map(|(_, ty)| ty);
+                let vec = self.parse_fields(types, data, |i| format!(".{}", fields[i].0))?;
 
-            let mut vec = Vec::with_capacity(vec_ty.len());
-            for (ty, ty_size) in vec_ty {
-                let len = ty_size.unwrap_or_else(|| data.len());
-                let val = parse(ty, &mut take_n(data, len)?)?;
-                vec.push(val);
+                let mut map = serde_json::Map::with_capacity(vec.len());
+                for ((name, _), val) in fields.iter().zip(vec) {
+                    map.insert(name.clone(), val);
+                }
+                Value::Object(map)
             }
-
-            if vec.is_empty() {
-                Value::Null
-            } else {
-                Value::Array(vec)
+            TypeDesc::Enum {
+                name,
+                variants,
+                width,
+            } => {
+                let data = &mut match width {
+                    Some(width) => self.take_n(data, *width)?,
+                    None => take_all(data),
+                };
+                let [tag] = self.take(data)?;
+                let Some((variant, ty)) = variants.get(tag as usize) else {
+                    let err = self.err(data, format!("a variant of {name}"), tag);
+                    return Err(err.back(1));
+                };
+                match ty {
+                    None => Value::String(variant.clone()),
+                    Some(ty) => {
+                        let val = self.parse(ty, data);
+                        let mut map = serde_json::Map::with_capacity(1);
+                        map.insert(
+                            variant.clone(),
+                            val.map_err(|err| err.within(format!("::{variant}")))?,
+                        );
+                        Value::Object(map)
+                    }
+                }
             }
-        }
-        Rule::Struct => {
-            let mut iter = p.into_inner();
-            let _struct_name = iter.next().unwrap();
-            let mut vec = Vec::new();
-            while iter.peek().is_some() {
-                let name = iter.next().unwrap();
-                let ty = iter.next().unwrap();
+            TypeDesc::Raw { width, .. } => Value::String(to_hex(match width {
+                Some(width) => self.take_n(data, *width)?,
+                None => take_all(data),
+            })),
+        })
+    }
 
-                let mut ty_size = parse_size(ty.clone())?;
-                if ty_size.is_none() && iter.peek().is_some() {
-                    ty_size = Some(take_varint(data)?);
+    /// Parse tuple or struct fields, whose variable width lengths are stored before the data
+    fn parse_fields<'b>(
+        &self,
+        fields: impl ExactSizeIterator<Item = &'b TypeDesc> + Clone,
+        data: &mut &'a [u8],
+        path: impl Fn(usize) -> String,
+    ) -> CodecResult<Vec<Value>> {
+        let mut lens = Vec::with_capacity(fields.len());
+        for (i, (ty, prefixed)) in fields
+            .clone()
+            .zip(TypeDesc::prefixed(fields.clone()))
+            .enumerate()
+        {
+            lens.push(match ty.width() {
+                Some(width) => Some(width),
+                None if prefixed => {
+                    Some(self.take_varint(data).map_err(|err| err.within(path(i)))?)
                 }
+                None => None,
+            });
+        }
 
-                vec.push((name.as_str(), ty, ty_size));
-            }
-
-            let mut map = serde_json::Map::with_capacity(vec.len());
-            for (name, ty, ty_size) in vec {
-                let len = ty_size.unwrap_or_else(|| data.len());
-                let val = parse(ty, &mut take_n(data, len)?)?;
-                map.insert(name.into(), val);
-            }
-            Value::Object(map)
+        let mut vec = Vec::with_capacity(lens.len());
+        for (i, (ty, len)) in fields.zip(lens).enumerate() {
+            let len = len.unwrap_or(data.len());
+            let val = self
+                .take_n(data, len)
+                .and_then(|mut data| self.parse(ty, &mut data));
+            vec.push(val.map_err(|err| err.within(path(i)))?);
         }
-        _ => return err_type(),
-    })
+        Ok(vec)
+    }
 }
 
-pub fn parse_size(p: Pair<'_, Rule>) -> Result<Option<usize>> {
-    let s = p.as_str();
+/// Bytes of an entry that can't be decoded as its type, e.g. `{"$raw": "ff00"}`
+pub fn raw_value(data: &[u8]) -> Value {
+    let mut map = serde_json::Map::with_capacity(1);
+    map.insert("$raw".into(), Value::String(to_hex(data)));
+    Value::Object(map)
+}
 
-    let err_type = || Err(eyre!("Unknown type {s:?}"));
+pub(crate) fn as_raw(val: &Value) -> Option<&str> {
+    match val.as_object()?.get("$raw")? {
+        Value::String(hex) if val.as_object()?.len() == 1 => Some(hex),
+        _ => None,
+    }
+}
 
-    Ok(match p.as_rule() {
-        Rule::Bool => Some(1),
-        Rule::Char => Some(<char as redb::Value>::fixed_width().unwrap()),
-        Rule::Int => {
-            let num: usize = s.strip_prefix(['u', 'i']).unwrap().parse().unwrap();
-            match num {
-                8 | 16 | 32 | 64 | 128 => Some(num / 8),
-                _ => return err_type(),
-            }
-        }
-        Rule::Float => match s {
-            "f32" => Some(4),
-            "f64" => Some(8),
-            _ => return err_type(),
-        },
-        Rule::String => None,
-        Rule::Slice => None,
-        Rule::Array => {
-            let mut iter = p.into_inner();
-            let ty = iter.next().unwrap();
-            let n = iter.next().unwrap();
-            assert_eq!(n.as_rule(), Rule::Num);
-            let n: usize = n.as_str().parse().unwrap();
-
-            parse_size(ty)?.map(|x| x * n)
-        }
-        Rule::Vec => None,
-        Rule::Option => {
-            let ty = p.into_inner().next().unwrap();
-            parse_size(ty)?.map(|x| 1 + x)
-        }
-        Rule::Tuple => {
-            let mut sum = 0;
-            for ty in p.into_inner() {
-                match parse_size(ty)? {
-                    Some(len) => sum += len,
-                    None => return Ok(None),
-                }
-            }
-            Some(sum)
-        }
-        Rule::Struct => {
-            let mut sum = 0;
-            let mut iter = p.into_inner();
-            let _struct_name = iter.next().unwrap();
-            while iter.peek().is_some() {
-                let _field_name = iter.next().unwrap();
-                let ty = iter.next().unwrap();
-                match parse_size(ty)? {
-                    Some(len) => sum += len,
-                    None => return Ok(None),
-                }
-            }
-            Some(sum)
-        }
-        _ => return err_type(),
+/// Non-finite floats have no JSON representation, so they are written as strings
+fn float_to_val(val: f64) -> Value {
+    match Number::from_f64(val) {
+        Some(num) => Value::Number(num),
+        None if val.is_nan() && val.is_sign_negative() => Value::String("-NaN".into()),
+        None if val.is_nan() => Value::String("NaN".into()),
+        None if val.is_sign_negative() => Value::String("-inf".into()),
+        None => Value::String("inf".into()),
+    }
+}
+
+fn string_to_float(s: &str) -> Option<f64> {
+    Some(match s {
+        "inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        "NaN" => f64::NAN,
+        "-NaN" => -f64::NAN,
+        _ => return None,
     })
 }
 
-pub fn can_order(p: Pair<'_, Rule>) -> Result<bool> {
-    let s = p.as_str();
+pub fn parse_size(ty: &TypeDesc) -> Option<usize> {
+    ty.width()
+}
 
-    let err_type = || Err(eyre!("Unknown type {s:?}"));
+/// Check the type tree against the width stored in the table definition, so unknown types
+/// parsed as unit structs, e.g. `Uuid`, aren't silently decoded
+pub fn check_width(ty: &TypeDesc, width: Option<usize>) -> Result<()> {
+    if ty.width() != width {
+        bail!(
+            "Type width {:?} doesn't match stored width {width:?}",
+            ty.width()
+        );
+    }
+    Ok(())
+}
 
-    Ok(match p.as_rule() {
-        Rule::Bool | Rule::Char | Rule::Int | Rule::String | Rule::Slice => true,
-        Rule::Float => false,
-        Rule::Array | Rule::Vec | Rule::Option => can_order(p.into_inner().next().unwrap())?,
-        Rule::Tuple => {
-            for ty in p.into_inner() {
-                if !can_order(ty)? {
-                    return Ok(false);
-                }
-            }
-            true
+pub fn can_order(ty: &TypeDesc) -> bool {
+    match ty {
+        TypeDesc::Bool
+        | TypeDesc::Char
+        | TypeDesc::Int { .. }
+        | TypeDesc::String
+        | TypeDesc::Slice
+        | TypeDesc::Float { .. } => true,
+        TypeDesc::Array { ty, .. } | TypeDesc::Vec(ty) | TypeDesc::Option { ty, .. } => {
+            can_order(ty)
         }
-        Rule::Struct => {
-            // Json objects don't preserve field order
-            false
+        TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+            fields.iter().all(can_order)
         }
-        _ => return err_type(),
-    })
+        // Fields are compared in the order of the type name, not of the Json object
+        TypeDesc::Struct { fields, .. } => fields.iter().all(|(_, ty)| can_order(ty)),
+        TypeDesc::Enum { variants, .. } => variants.iter().flat_map(|(_, ty)| ty).all(can_order),
+        TypeDesc::Raw { ty, .. } => ty.as_deref().is_some_and(can_order),
+    }
 }
 
-pub fn ordering(l: &Value, r: &Value) -> Option<std::cmp::Ordering> {
-    Some(match (l, r) {
-        (Value::Null, Value::Null) => Ordering::Equal,
-        (Value::Null, t) if !t.is_null() => Ordering::Less,
-        (t, Value::Null) if !t.is_null() => Ordering::Greater,
-
-        (Value::Bool(l), Value::Bool(r)) => Ord::cmp(l, r),
-        (Value::String(l), Value::String(r)) => Ord::cmp(l, r),
+/// Ordering of values of the type, the same as of their encoding
+pub fn ordering(ty: &TypeDesc, l: &Value, r: &Value) -> Result<Ordering> {
+    let (mut l_buf, mut r_buf) = (Vec::new(), Vec::new());
+    encode(ty, l, &mut l_buf)?;
+    encode(ty, r, &mut r_buf)?;
+    Ok(ty.compare(&l_buf, &r_buf))
+}
 
-        (Value::Number(l), Value::Number(r)) => {
-            if let Some((l, r)) = l.as_i128().zip(r.as_i128()) {
-                Ord::cmp(&l, &r)
-            } else if let Some((l, r)) = l.as_u128().zip(r.as_u128()) {
-                Ord::cmp(&l, &r)
+/// Smallest value of the type with respect to [`TypeDesc::compare`]
+pub fn min_value(ty: &TypeDesc) -> Result<Value> {
+    Ok(match ty {
+        TypeDesc::Bool => Value::Bool(false),
+        TypeDesc::Char => Value::String('\0'.into()),
+        TypeDesc::Int { signed: false, .. } => Value::Number(0.into()),
+        TypeDesc::Int { bits, signed: true } => {
+            let num = i128::MIN >> (128 - bits);
+            Value::Number(Number::from_i128(num).unwrap())
+        }
+        // The negative NaN with every payload bit set, which no JSON value spells
+        TypeDesc::Float { bits } => raw_value(&vec![0xff; bits / 8]),
+        TypeDesc::String => Value::String(String::new()),
+        TypeDesc::Slice | TypeDesc::Vec(_) => Value::Array(Vec::new()),
+        TypeDesc::Option { .. } => Value::Null,
+        TypeDesc::Array { ty, n, .. } => Value::Array(vec![min_value(ty)?; *n]),
+        TypeDesc::TupleStruct { fields, .. } if fields.len() == 1 => min_value(&fields[0])?,
+        TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. } => {
+            let vec = fields.iter().map(min_value).collect::<Result<Vec<_>>>()?;
+            if vec.is_empty() {
+                Value::Null
             } else {
-                unreachable!()
+                Value::Array(vec)
             }
         }
+        TypeDesc::Struct { fields, .. } => {
+            let mut map = serde_json::Map::with_capacity(fields.len());
+            for (name, ty) in fields {
+                map.insert(name.clone(), min_value(ty)?);
+            }
+            Value::Object(map)
+        }
+        TypeDesc::Enum { name, variants, .. } => match variants.first() {
+            None => bail!("Enum {name:?} has no variants"),
+            Some((variant, None)) => Value::String(variant.clone()),
+            Some((variant, Some(ty))) => {
+                let mut map = serde_json::Map::with_capacity(1);
+                map.insert(variant.clone(), min_value(ty)?);
+                Value::Object(map)
+            }
+        },
+        TypeDesc::Raw { .. } => bail!("Type {ty:?} can't be ordered"),
+    })
+}
 
-        (Value::Array(l), Value::Array(r)) => {
-            for (l, r) in Iterator::zip(l.iter(), r.iter()) {
-                let c = ordering(l, r)?;
-                if !c.is_eq() {
-                    return Some(c);
+/// Complete a tuple prefix, e.g. `[1]` of `(u64, i32)`, or a struct prefix, e.g. `{"id": 1}`
+/// of `User {id: u64, name: String}`, with the smallest values
+pub fn pad_prefix(ty: &TypeDesc, val: Value) -> Result<Value> {
+    match (ty, val) {
+        (TypeDesc::TupleStruct { fields, .. }, val) if fields.len() == 1 => {
+            pad_prefix(&fields[0], val)
+        }
+        (
+            TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. },
+            Value::Array(mut vec),
+        ) => {
+            for ty in fields.iter().skip(vec.len()) {
+                vec.push(min_value(ty)?);
+            }
+            Ok(Value::Array(vec))
+        }
+        // Like tuples, only the last fields in declaration order can be left out
+        (TypeDesc::Struct { fields, .. }, Value::Object(mut map)) => {
+            let mut missing = None;
+            for (name, ty) in fields {
+                match (map.contains_key(name), missing) {
+                    (true, Some(missing)) => bail!("Missing field {missing:?} before {name:?}"),
+                    (true, None) => {}
+                    (false, _) => {
+                        missing = missing.or(Some(name));
+                        map.insert(name.clone(), min_value(ty)?);
+                    }
                 }
             }
-            Ord::cmp(&l.len(), &r.len())
+            Ok(Value::Object(map))
         }
-
-        (Value::Object(_), Value::Object(_)) => return None,
-
-        _ => unreachable!(),
-    })
+        (_, val) => Ok(val),
+    }
 }
@@ -1,76 +1,609 @@
-use std::io::Write;
+use clap::ValueEnum;
+use eyre::{WrapErr, bail};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::io::{BufWriter, Write};
 
-use crate::{Data, KVType, cli::CliArgs, transcode::val_to_string};
+use crate::{
+    Data, KVType,
+    cli::CliArgs,
+    dump::Header,
+    filter::{Fields, Filter},
+    parser::TypeDesc,
+    transcode::{K_TREE, V_TREE, val_to_string},
+};
 
-pub fn print(args: &CliArgs, data: Data) -> eyre::Result<()> {
-    if args.delete || args.remove {
-        return Ok(());
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// `key: value` lines
+    #[default]
+    Text,
+    /// Object of entries with stringified keys
+    Json,
+    /// Array of typed key-value pairs
+    Pairs,
+    /// One key-value pair per line
+    Ndjson,
+    /// Comma-separated rows, with a column per field of struct and tuple keys and values
+    Csv,
+    /// Tab-separated rows, with a column per field of struct and tuple keys and values
+    Tsv,
+    /// Sequence of key-value pairs
+    Yaml,
+    /// Lines of [`crate::dump::Line`], a header for each table followed by its entries
+    #[value(skip)]
+    Dump,
+}
+
+impl Format {
+    fn delimiter(self) -> Option<u8> {
+        match self {
+            Format::Csv => Some(b','),
+            Format::Tsv => Some(b'\t'),
+            _ => None,
+        }
     }
+}
 
-    let Data {
-        stats,
-        list,
-        out,
-        types,
-    } = data;
+fn csv_writer<W: Write>(delimiter: u8, out: W) -> csv::Writer<W> {
+    csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(out)
+}
 
-    if args.json {
-        let mut stdout = std::io::stdout().lock();
+/// A single NDJSON record or YAML sequence item
+#[derive(Serialize)]
+struct Line<'a, T = Value> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table: Option<&'a str>,
+    key: &'a T,
+    value: &'a T,
+}
+
+/// Serializes JSON numbers as numbers, which `arbitrary_precision` turns into maps otherwise
+struct Yaml<'a>(&'a Value);
 
-        match (&args.table, &args.key, &args.value) {
-            _ if args.list => serde_json::to_writer_pretty(&mut stdout, &list)?,
-            _ if args.stats => serde_json::to_writer_pretty(&mut stdout, &stats)?,
-            (None, _, _) => serde_json::to_writer_pretty(&mut stdout, &out)?,
-            (Some(t), None, _) => serde_json::to_writer_pretty(&mut stdout, &out[t])?,
-            (Some(t), Some(k), None) => serde_json::to_writer_pretty(&mut stdout, &out[t][k])?,
-            (Some(_t), Some(_k), Some(_v)) => return Ok(()),
+impl Serialize for Yaml<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    s.serialize_u64(n)
+                } else if let Some(n) = n.as_i64() {
+                    s.serialize_i64(n)
+                } else if let Some(n) = n.as_u128() {
+                    s.serialize_u128(n)
+                } else if let Some(n) = n.as_i128() {
+                    s.serialize_i128(n)
+                } else if let Some(n) = n.as_f64() {
+                    s.serialize_f64(n)
+                } else {
+                    s.serialize_str(n.as_str())
+                }
+            }
+            Value::Array(vec) => s.collect_seq(vec.iter().map(Yaml)),
+            Value::Object(map) => s.collect_map(map.iter().map(|(k, v)| (k, Yaml(v)))),
+            val => val.serialize(s),
         }
-        writeln!(stdout)?;
-        return Ok(());
     }
+}
+
+/// CSV column of a key or value, or of one of their fields when they are flattened
+struct Column {
+    /// Named like the targets of `load --column`, so the output can be loaded back
+    name: String,
+    is_key: bool,
+    /// Object keys and array indices leading to the field
+    path: Vec<String>,
+    ty: TypeDesc,
+}
 
-    if args.list {
-        for (table, types) in &list {
-            println!("{table}: {} -> {}", types[0], types[1]);
+impl Column {
+    fn flatten(self, out: &mut Vec<Column>) {
+        let field = |name: String, seg: Option<String>, ty: &TypeDesc| Column {
+            name: format!("{}.{name}", self.name),
+            is_key: self.is_key,
+            path: self.path.iter().cloned().chain(seg).collect(),
+            ty: ty.clone(),
+        };
+        match &self.ty {
+            TypeDesc::Struct { fields, .. } if !fields.is_empty() => {
+                for (name, ty) in fields {
+                    field(name.clone(), Some(name.clone()), ty).flatten(out);
+                }
+            }
+            // Newtype structs are shown as the field itself
+            TypeDesc::TupleStruct { fields, .. } if fields.len() == 1 => {
+                field("0".into(), None, &fields[0]).flatten(out);
+            }
+            TypeDesc::Tuple { fields, .. } | TypeDesc::TupleStruct { fields, .. }
+                if !fields.is_empty() =>
+            {
+                for (i, ty) in fields.iter().enumerate() {
+                    field(i.to_string(), Some(i.to_string()), ty).flatten(out);
+                }
+            }
+            _ => out.push(self),
         }
-        return Ok(());
     }
 
-    if args.stats {
-        for (table, out) in &stats {
-            println!();
-            println!("{table}:");
-            for (k, v) in out {
-                println!("{k}: {v}");
+    /// Strings are written as is, other types as JSON, and null options as empty cells
+    fn cell(&self, root: &Value) -> eyre::Result<String> {
+        let Some(val) = self.path.iter().try_fold(root, |val, seg| match val {
+            Value::Object(map) => map.get(seg),
+            Value::Array(vec) => vec.get(seg.parse::<usize>().ok()?),
+            _ => None,
+        }) else {
+            // Entries that failed to decode don't have the fields
+            return Ok(String::new());
+        };
+        let mut ty = &self.ty;
+        while let TypeDesc::Option { ty: inner, .. } = ty {
+            if val.is_null() {
+                return Ok(String::new());
             }
+            ty = inner;
         }
-        return Ok(());
+        Ok(match (ty, val) {
+            (TypeDesc::String | TypeDesc::Char | TypeDesc::Raw { .. }, Value::String(s)) => {
+                s.clone()
+            }
+            _ => serde_json::to_string(val)?,
+        })
+    }
+}
+
+/// Writes entries as they are read from the table, so memory use doesn't depend on table size.
+///
+/// JSON formats are emitted with the same layout as [`serde_json::to_writer_pretty`].
+pub struct Printer {
+    out: Box<dyn Write>,
+    format: Format,
+    /// Prefix entries with the table name, used when processing every table
+    all_tables: bool,
+    /// Print only values, used when looking up a single key
+    value_only: bool,
+
+    ty: Option<KVType<&'static String>>,
+    table: String,
+    key: Value,
+    tables: usize,
+    /// Entries of the current table
+    entries: usize,
+    /// Whether the current entry is written, which waits for a value that passes the filter
+    entry_open: bool,
+    /// Open JSON containers: closing bracket and number of items written
+    stack: Vec<(char, usize)>,
+    /// Takes over the output for CSV and TSV
+    csv: Option<csv::Writer<Box<dyn Write>>>,
+    columns: Vec<Column>,
+    filter: Option<Filter>,
+    fields: Fields,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self {
+            out: Box::new(BufWriter::new(std::io::stdout())),
+            format: Format::default(),
+            all_tables: false,
+            value_only: false,
+            ty: None,
+            table: String::new(),
+            key: Value::Null,
+            tables: 0,
+            entries: 0,
+            entry_open: false,
+            stack: Vec::new(),
+            csv: None,
+            columns: Vec::new(),
+            filter: None,
+            fields: Fields::default(),
+        }
+    }
+}
+
+impl Printer {
+    pub fn new(args: &CliArgs) -> eyre::Result<Self> {
+        Self::with_writer(args, Box::new(BufWriter::new(std::io::stdout())))
+    }
+
+    pub fn with_writer(args: &CliArgs, out: Box<dyn Write>) -> eyre::Result<Self> {
+        let format = if args.dump { Format::Dump } else { args.format };
+        let filter = args.filter.as_deref().map(Filter::new).transpose();
+        let mut printer = Self {
+            out,
+            format,
+            all_tables: args.table.is_none(),
+            value_only: args.key.is_some(),
+            filter: filter.wrap_err("Invalid filter")?,
+            fields: Fields::new(&args.fields),
+            ..Default::default()
+        };
+        if let Some(delimiter) = format.delimiter() {
+            let out = std::mem::replace(&mut printer.out, Box::new(std::io::sink()));
+            printer.csv = Some(csv_writer(delimiter, out));
+        }
+        Ok(printer)
     }
 
-    for (table, out) in out {
-        if args.table.is_none() {
-            println!();
-            println!("{table}:");
+    pub fn begin_table(&mut self, table: &str, ty: KVType<&'static String>) -> eyre::Result<()> {
+        self.ty = Some(ty);
+        self.table.clear();
+        self.table.push_str(table);
+        self.entries = 0;
+        if !self.fields.is_empty() {
+            V_TREE
+                .with_borrow(|t| self.fields.check(t.as_ref().unwrap()))
+                .wrap_err_with(|| format!("Can't select fields of {table:?}"))?;
         }
-        let KVType { v_ty, is_multi, .. } = types.get(&table).unwrap();
-        for (k, v) in out {
-            if *is_multi {
-                for v in v.as_array().unwrap() {
-                    let v = val_to_string(v_ty, v.clone());
-                    if args.key.is_none() {
-                        print!("{k}: ");
+
+        match self.format {
+            Format::Text if self.all_tables => {
+                writeln!(self.out)?;
+                writeln!(self.out, "{table}:")?;
+            }
+            Format::Json | Format::Pairs => {
+                if self.all_tables {
+                    if self.tables == 0 {
+                        self.open('{')?;
                     }
-                    println!("{v}");
+                    self.item(Some(table))?;
+                }
+                match self.format {
+                    Format::Json if !self.value_only => self.open('{')?,
+                    Format::Pairs => self.open('[')?,
+                    _ => {}
+                }
+            }
+            Format::Csv | Format::Tsv => {
+                if self.all_tables {
+                    bail!("CSV and TSV output need a table, since tables have different columns");
+                }
+                self.columns.clear();
+                for (is_key, name, tree) in [(true, "key", &K_TREE), (false, "value", &V_TREE)] {
+                    let column = Column {
+                        name: name.into(),
+                        is_key,
+                        path: Vec::new(),
+                        ty: tree.with_borrow(|t| t.clone().unwrap()),
+                    };
+                    column.flatten(&mut self.columns);
+                }
+                self.columns
+                    .retain(|c| c.is_key || self.fields.keeps(&c.path));
+                let header = self.columns.iter().map(|c| &c.name);
+                self.csv.as_mut().unwrap().write_record(header)?;
+            }
+            Format::Yaml if self.all_tables => {
+                let table = serde_yaml_ng::to_string(table)?;
+                write!(self.out, "{}:", table.trim_end())?;
+            }
+            _ => {}
+        }
+        self.tables += 1;
+
+        Ok(())
+    }
+
+    pub fn dump_header(&mut self, header: &Header) -> eyre::Result<()> {
+        serde_json::to_writer(&mut self.out, header)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    pub fn begin_entry(&mut self, key: Value) -> eyre::Result<()> {
+        self.key = key;
+        self.entry_open = false;
+        Ok(())
+    }
+
+    /// Start the entry once its first value passes the filter
+    fn open_entry(&mut self) -> eyre::Result<()> {
+        let KVType { k_ty, is_multi, .. } = self.ty.unwrap();
+        let key = std::mem::take(&mut self.key);
+
+        match self.format {
+            Format::Json => {
+                if !self.value_only {
+                    self.item(Some(&val_to_string(k_ty, key.clone())))?;
+                }
+                if is_multi {
+                    self.open('[')?;
+                }
+            }
+            Format::Pairs => {
+                self.item(None)?;
+                self.open('{')?;
+                self.item(Some("key"))?;
+                self.value(&key)?;
+                self.item(Some("value"))?;
+                if is_multi {
+                    self.open('[')?;
+                }
+            }
+            Format::Text
+            | Format::Ndjson
+            | Format::Csv
+            | Format::Tsv
+            | Format::Yaml
+            | Format::Dump => {}
+        }
+        self.key = key;
+        self.entry_open = true;
+
+        Ok(())
+    }
+
+    pub fn entry_value(&mut self, mut val: Value) -> eyre::Result<()> {
+        let KVType {
+            k_ty,
+            v_ty,
+            is_multi,
+        } = self.ty.unwrap();
+
+        if let Some(filter) = &self.filter
+            && !filter.matches(&mut self.key, &mut val).wrap_err_with(|| {
+                format!("Can't filter key {}", val_to_string(k_ty, self.key.clone()))
+            })?
+        {
+            return Ok(());
+        }
+        let val = self.fields.project(val);
+        if !self.entry_open {
+            self.open_entry()?;
+        }
+
+        match self.format {
+            Format::Text => {
+                if !self.value_only {
+                    write!(self.out, "{}: ", val_to_string(k_ty, self.key.clone()))?;
+                }
+                writeln!(self.out, "{}", val_to_string(v_ty, val))?;
+            }
+            Format::Json | Format::Pairs => {
+                if is_multi {
+                    self.item(None)?;
+                }
+                self.value(&val)?;
+            }
+            Format::Ndjson | Format::Dump => {
+                let line = Line {
+                    table: (self.all_tables && self.format == Format::Ndjson)
+                        .then_some(self.table.as_str()),
+                    key: &self.key,
+                    value: &val,
+                };
+                serde_json::to_writer(&mut self.out, &line)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv | Format::Tsv => {
+                let row = self
+                    .columns
+                    .iter()
+                    .map(|c| c.cell(if c.is_key { &self.key } else { &val }))
+                    .collect::<eyre::Result<Vec<_>>>()?;
+                self.csv.as_mut().unwrap().write_record(row)?;
+            }
+            Format::Yaml => {
+                if self.all_tables && self.entries == 0 {
+                    writeln!(self.out)?;
                 }
+                let indent = if self.all_tables { "  " } else { "" };
+                let line = Line {
+                    table: None,
+                    key: &Yaml(&self.key),
+                    value: &Yaml(&val),
+                };
+                for (i, line) in serde_yaml_ng::to_string(&line)?.lines().enumerate() {
+                    let bullet = if i == 0 { "- " } else { "  " };
+                    writeln!(self.out, "{indent}{bullet}{line}")?;
+                }
+            }
+        }
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    pub fn end_entry(&mut self) -> eyre::Result<()> {
+        let KVType { is_multi, .. } = self.ty.unwrap();
+        if !self.entry_open {
+            return Ok(());
+        }
+
+        if matches!(self.format, Format::Json | Format::Pairs) && is_multi {
+            self.close()?;
+        }
+        if self.format == Format::Pairs {
+            self.close()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn end_table(&mut self) -> eyre::Result<()> {
+        match self.format {
+            Format::Json if !self.value_only => self.close()?,
+            Format::Pairs => self.close()?,
+            Format::Yaml if self.entries == 0 => {
+                let space = if self.all_tables { " " } else { "" };
+                writeln!(self.out, "{space}[]")?;
+            }
+            _ => {}
+        }
+        self.ty = None;
+
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> eyre::Result<()> {
+        if matches!(self.format, Format::Json | Format::Pairs) {
+            if self.all_tables && self.tables == 0 {
+                self.open('{')?;
+            }
+            while !self.stack.is_empty() {
+                self.close()?;
+            }
+            // A single value that didn't pass the filter isn't printed at all
+            let filtered_out = self.format == Format::Json && self.value_only && self.entries == 0;
+            if self.all_tables || (self.tables > 0 && !filtered_out) {
+                writeln!(self.out)?;
+            }
+        }
+        if self.format == Format::Yaml && self.all_tables && self.tables == 0 {
+            writeln!(self.out, "{{}}")?;
+        }
+        if let Some(csv) = &mut self.csv {
+            csv.flush()?;
+        }
+        self.out.flush()?;
+
+        Ok(())
+    }
+
+    fn indent(&mut self) -> std::io::Result<()> {
+        writeln!(self.out)?;
+        for _ in 0..self.stack.len() {
+            write!(self.out, "  ")?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, bracket: char) -> std::io::Result<()> {
+        let close = if bracket == '{' { '}' } else { ']' };
+        write!(self.out, "{bracket}")?;
+        self.stack.push((close, 0));
+        Ok(())
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        let (close, count) = self.stack.pop().unwrap();
+        if count > 0 {
+            self.indent()?;
+        }
+        write!(self.out, "{close}")
+    }
+
+    fn item(&mut self, key: Option<&str>) -> eyre::Result<()> {
+        let (_, count) = self.stack.last_mut().unwrap();
+        *count += 1;
+        if *count > 1 {
+            write!(self.out, ",")?;
+        }
+        self.indent()?;
+        if let Some(key) = key {
+            serde_json::to_writer(&mut self.out, key)?;
+            write!(self.out, ": ")?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, val: &Value) -> eyre::Result<()> {
+        let mut indent = String::from("\n");
+        indent.extend(std::iter::repeat_n("  ", self.stack.len()));
+        let val = serde_json::to_string_pretty(val)?;
+        write!(self.out, "{}", val.replace('\n', &indent))?;
+        Ok(())
+    }
+}
+
+pub fn print(args: &CliArgs, mut data: Data) -> eyre::Result<()> {
+    if let Some(changes) = data.changes.take() {
+        let counts = changes.finish()?;
+        eprintln!("Dry run, nothing was written: {counts}");
+        return Ok(());
+    }
+    if args.delete || args.edit || args.remove {
+        return Ok(());
+    }
+
+    let Data {
+        stats,
+        list,
+        mut printer,
+        ..
+    } = data;
+
+    if !args.list && !args.stats {
+        return printer.finish();
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    match printer.format {
+        Format::Json | Format::Pairs | Format::Ndjson => {
+            if args.list {
+                serde_json::to_writer_pretty(&mut stdout, &list)?;
+            } else {
+                serde_json::to_writer_pretty(&mut stdout, &stats)?;
+            }
+            writeln!(stdout)?;
+        }
+        Format::Yaml => {
+            if args.list {
+                serde_yaml_ng::to_writer(&mut stdout, &list)?;
             } else {
-                let v = val_to_string(v_ty, v);
-                if args.key.is_none() {
-                    print!("{k}: ");
+                serde_yaml_ng::to_writer(&mut stdout, &stats)?;
+            }
+        }
+        Format::Csv | Format::Tsv => {
+            let mut csv = csv_writer(printer.format.delimiter().unwrap(), stdout);
+            if args.list {
+                csv.write_record(["table", "key", "value"])?;
+                for (table, types) in &list {
+                    csv.write_record([table, &types[0], &types[1]])?;
+                }
+            } else {
+                csv.write_record(["table", "stat", "value"])?;
+                for (table, out) in &stats {
+                    for (k, v) in out {
+                        csv.write_record([table, k, &v.to_string()])?;
+                    }
+                }
+            }
+            csv.flush()?;
+        }
+        Format::Text | Format::Dump if args.list => {
+            for (table, types) in &list {
+                writeln!(stdout, "{table}: {} -> {}", types[0], types[1])?;
+            }
+        }
+        Format::Text | Format::Dump => {
+            for (table, out) in &stats {
+                writeln!(stdout)?;
+                writeln!(stdout, "{table}:")?;
+                for (k, v) in out {
+                    writeln!(stdout, "{k}: {v}")?;
                 }
-                println!("{v}");
             }
         }
     }
 
     Ok(())
 }
+
+/// Ids of persistent savepoints
+pub fn print_savepoints(args: &CliArgs, ids: &[u64]) -> eyre::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    match args.format {
+        Format::Json | Format::Pairs | Format::Ndjson => {
+            serde_json::to_writer(&mut stdout, ids)?;
+            writeln!(stdout)?;
+        }
+        Format::Yaml => serde_yaml_ng::to_writer(&mut stdout, ids)?,
+        Format::Csv | Format::Tsv => {
+            let mut csv = csv_writer(args.format.delimiter().unwrap(), stdout);
+            csv.write_record(["id"])?;
+            for id in ids {
+                csv.write_record([id.to_string()])?;
+            }
+            csv.flush()?;
+        }
+        Format::Text | Format::Dump => {
+            for id in ids {
+                writeln!(stdout, "{id}")?;
+            }
+        }
+    }
+    Ok(())
+}
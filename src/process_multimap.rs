@@ -1,15 +1,21 @@
-use eyre::{OptionExt, Result, bail};
+use eyre::{OptionExt, Result, bail, eyre};
 use redb::{
-    MultimapTableDefinition, MultimapTableHandle, ReadableMultimapTable, ReadableTableMetadata,
-    TableError, TypeName, Value,
+    MultimapTable, MultimapTableDefinition, MultimapTableHandle, ReadableMultimapTable,
+    ReadableTableMetadata, TableError, Value,
 };
+use std::{num::NonZeroUsize, ops::Bound};
 
 use crate::{
     DB, Data, KVType, WARNING,
+    changes::Changes,
     cli::CliArgs,
-    parser,
+    delete, dump, edit,
+    filter::Filter,
+    load,
+    parser::{self, TypeDesc},
     transcode::{
-        K, K_NAME, K_TREE, K_WIDTH, V, V_NAME, V_TREE, V_WIDTH, string_to_val, val_to_string,
+        K, K_NAME, K_TREE, K_WIDTH, V, V_NAME, V_TREE, V_WIDTH, check_key, check_value, set_schema,
+        string_to_val, val_to_string,
     },
 };
 
@@ -37,54 +43,8 @@ pub fn process_multimap_table(
     data: &mut Data,
     table_name: &str,
 ) -> Result<()> {
-    K_NAME.set(String::type_name());
-    K_WIDTH.set(None);
-    K_TREE.set(None);
-
-    V_NAME.set(String::type_name());
-    V_WIDTH.set(None);
-    V_TREE.set(None);
-
-    if let Some(schema) = &args.schema {
-        let (k_ty, v_ty) = schema
-            .split_once(" -> ")
-            .ok_or_eyre("Use -> to separate key and value types")?;
-
-        let k_tree = parser::parse_tree(k_ty)?;
-        let v_tree = parser::parse_tree(v_ty)?;
-
-        K_NAME.set(TypeName::new(k_ty));
-        K_WIDTH.set(parser::parse_size(k_tree)?);
-
-        V_NAME.set(TypeName::new(v_ty));
-        V_WIDTH.set(parser::parse_size(v_tree)?);
-    }
-
+    resolve_multimap_types(args, db, table_name)?;
     let table_def = MultimapTableDefinition::<K, V>::new(table_name);
-    for _ in 0..5 {
-        match db.begin_read()?.open_multimap_table(table_def) {
-            Err(TableError::TableTypeMismatch { key, value, .. }) if args.schema.is_none() => {
-                K_NAME.set(key);
-                V_NAME.set(value)
-            }
-            Err(TableError::TypeDefinitionChanged { name, width, .. }) if args.schema.is_none() => {
-                if K_NAME.with_borrow(|n| n == &name) {
-                    K_WIDTH.set(width);
-                }
-                if V_NAME.with_borrow(|n| n == &name) {
-                    V_WIDTH.set(width);
-                }
-            }
-            Err(TableError::TableDoesNotExist(_)) if args.create => {
-                eprintln!("Creating table {table_name:?}");
-                let w = db.begin_write()?;
-                w.open_multimap_table(table_def)?;
-                w.commit()?;
-            }
-            Err(err) => return Err(err.into()),
-            Ok(_) => break,
-        }
-    }
 
     if args.list {
         let out = data.list.entry(table_name.to_string()).or_default();
@@ -94,64 +54,88 @@ pub fn process_multimap_table(
         return Ok(());
     }
 
-    let k_ty = Box::leak(Box::new(String::new()));
-    let v_ty = Box::leak(Box::new(String::new()));
+    let ty = parse_multimap_types(args, table_name);
 
-    {
-        let k = K_NAME.with_borrow(|n| n.clone());
-        k_ty.push_str(k.name());
-        let k_tree = parser::parse_tree(k_ty)?;
-
-        let v = V_NAME.with_borrow(|n| n.clone());
-        v_ty.push_str(v.name());
-
-        if let Err(err) = parser::parse_tree(v_ty) {
-            v_ty.clear();
-            v_ty.push_str("String");
-            eprintln!(
-                "{WARNING} Error parsing value type {:?}, defaulting to {:?}: {err}",
-                v.name(),
-                v_ty,
-            );
-        }
+    // Entries of unknown types can still be listed, but not looked up
+    let lookup = args.key.is_some() || args.from.is_some() || args.to.is_some();
+    if lookup && !K_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+        bail!(
+            "Key type {:?} of table {table_name:?} can't be ordered",
+            K::type_name().name(),
+        );
+    }
 
-        let v_tree = parser::parse_tree(v_ty)?;
+    // or #[cfg(false)]
+    if args.value.is_some() && !V_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+        bail!(
+            "Value type {:?} of table {table_name:?} can't be ordered",
+            V::type_name().name(),
+        );
+    }
 
-        if !parser::can_order(k_tree.clone())? {
-            eprintln!(
-                "{WARNING} Key type {:?} of table {table_name:?} can't be ordered.",
-                k.name(),
-            );
-            return Ok(());
-        }
+    let ty = *data.types.entry(table_name.to_string()).or_insert(ty);
+    let KVType { k_ty, v_ty, .. } = ty;
+    if let Some(changes) = &mut data.changes {
+        changes.begin_table(table_name, ty);
+    }
 
-        // or #[cfg(false)]
-        if !parser::can_order(v_tree.clone())? {
-            eprintln!(
-                "{WARNING} Value type {:?} of table {table_name:?} can't be ordered.",
-                k.name(),
-            );
-            return Ok(());
-        }
+    if args.delete {
+        let w = db.begin_write()?;
+        w.delete_multimap_table(w.open_multimap_table(table_def)?)?;
+        w.commit()?;
+        return Ok(());
+    }
 
-        K_TREE.set(Some(k_tree));
-        V_TREE.set(Some(v_tree));
+    if let Some(load) = &args.load {
+        let batch = load.commit_every.map_or(usize::MAX, NonZeroUsize::get);
+        // Dry runs are rolled back as a whole
+        let batch = if args.dry_run { usize::MAX } else { batch };
+        let mut entries = load::entries(load)?.peekable();
+        let mut progress = load::Progress::new(table_name);
+        let mut count = 0;
+        loop {
+            let w = db.begin_write()?;
+            let mut table = w.open_multimap_table(table_def)?;
+            for entry in entries.by_ref().take(batch) {
+                let (k, v) = entry?;
+                insert_multimap(&mut table, &k, &v, data.changes.as_mut())?;
+                count += 1;
+                progress.update(count);
+            }
+            drop(table);
+            crate::commit(w, args.dry_run)?;
+            if entries.peek().is_none() {
+                break;
+            }
+        }
+        progress.finish(count, args.dry_run);
+        return Ok(());
     }
 
-    let KVType { k_ty, v_ty, .. } = *data.types.entry(table_name.to_string()).or_insert(KVType {
-        k_ty,
-        v_ty,
-        is_multi: true, // or is_multi: false,
-    });
+    if args.edit {
+        let k = check_key(string_to_val(k_ty, args.key.as_deref().unwrap())?)?;
+        let key = val_to_string(k_ty, k.clone());
+        let current = get_multimap(&db.begin_read()?.open_multimap_table(table_def)?, &k)?
+            .ok_or_else(|| eyre!("No such key {key:?} in {table_name:?}"))?;
+
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".into());
+        let Some(edited) = edit::edit(&editor, &current, check_multimap)? else {
+            eprintln!("No changes to {key:?}");
+            return Ok(());
+        };
 
-    if args.delete {
         let w = db.begin_write()?;
-        w.delete_multimap_table(w.open_multimap_table(table_def)?)?;
+        let mut table = w.open_multimap_table(table_def)?;
+        if get_multimap(&table, &k)?.as_ref() != Some(&current) {
+            bail!("Key {key:?} of {table_name:?} changed while editing, not writing");
+        }
+        put_multimap(&mut table, &k, edited)?;
+        drop(table);
         w.commit()?;
         return Ok(());
     }
 
-    let out = data.out.entry(table_name.to_string()).or_default();
+    let out = &mut data.printer;
     let r = db.begin_read()?;
     let table = r.open_multimap_table(table_def)?;
 
@@ -174,25 +158,67 @@ pub fn process_multimap_table(
         return Ok(());
     }
 
+    let bound = |k: &Option<String>| -> Result<Option<serde_json::Value>> {
+        let Some(k) = k else { return Ok(None) };
+        let k = string_to_val(k_ty, k)?;
+        let k = K_TREE.with_borrow(|t| parser::pad_prefix(t.as_ref().unwrap(), k))?;
+        Ok(Some(check_key(k)?))
+    };
+    let from = bound(&args.from)?.map_or(Bound::Unbounded, Bound::Included);
+    let to = bound(&args.to)?.map_or(Bound::Unbounded, Bound::Excluded);
+
+    // Ranges and filters remove any number of entries
+    let bulk = args.key.is_none() || args.filter.is_some();
+    if args.remove && bulk {
+        if args.value.is_some() {
+            bail!("A single value can't be removed with --filter");
+        }
+        drop(table);
+        drop(r);
+        let (from, to) = match &args.key {
+            Some(k) => {
+                let k = check_key(string_to_val(k_ty, k)?)?;
+                (Bound::Included(k.clone()), Bound::Included(k))
+            }
+            None => (from, to),
+        };
+        let filter = args.filter.as_deref().map(Filter::new).transpose()?;
+
+        let w = db.begin_write()?;
+        let mut table = w.open_multimap_table(table_def)?;
+        let count = delete::remove_multimap(
+            &mut table,
+            (from, to),
+            filter.as_ref(),
+            k_ty,
+            data.changes.as_mut(),
+        )?;
+        drop(table);
+        crate::commit(w, args.dry_run)?;
+        if !args.dry_run {
+            eprintln!("Removed {count} entries from {table_name:?}");
+        }
+        return Ok(());
+    }
+
     match (&args.key, &args.value) {
         (None, _) => {
-            for r in table.iter()? {
+            if args.dump {
+                out.dump_header(&dump::Header::current(table_name, ty.is_multi))?;
+            }
+            out.begin_table(table_name, ty)?;
+            for r in table.range::<serde_json::Value>((from, to))? {
                 let (k, v) = r?;
-                let k = val_to_string(k_ty, k.value());
-
-                // or out.entry(k).or_insert(v.value());
-                // or #[cfg(false)]
-                let vs = out
-                    .entry(k)
-                    .or_insert(serde_json::Value::Array(Vec::new()))
-                    .as_array_mut()
-                    .unwrap();
 
+                out.begin_entry(k.value())?;
+                // or out.entry_value(v.value())?;
                 // or #[cfg(false)]
                 for v in v {
-                    vs.push(v?.value());
+                    out.entry_value(v?.value())?;
                 }
+                out.end_entry()?;
             }
+            out.end_table()?;
         }
         // or #[cfg(false)]
         (Some(k), Some(v)) if args.remove => {
@@ -200,13 +226,16 @@ pub fn process_multimap_table(
             drop(r);
             let w = db.begin_write()?;
             let mut table = w.open_multimap_table(table_def)?;
-            let k = string_to_val(k_ty, k)?;
-            let v = string_to_val(v_ty, v)?;
+            let k = check_key(string_to_val(k_ty, k)?)?;
+            let v = check_value(string_to_val(v_ty, v)?)?;
             if !table.remove(&k, &v)? {
                 bail!("No such key {:?} in {table_name:?}", val_to_string(k_ty, k));
             }
+            if let Some(changes) = &mut data.changes {
+                changes.remove(&k, &v)?;
+            }
             drop(table);
-            w.commit()?;
+            crate::commit(w, args.dry_run)?;
             return Ok(());
         }
         (Some(k), _) if args.remove => {
@@ -214,47 +243,208 @@ pub fn process_multimap_table(
             drop(r);
             let w = db.begin_write()?;
             let mut table = w.open_multimap_table(table_def)?;
-            let k = string_to_val(k_ty, k)?;
-            if table.remove_all(&k)?.is_empty() {
+            let k = check_key(string_to_val(k_ty, k)?)?;
+            if !remove_all_multimap(&mut table, &k, data.changes.as_mut())? {
                 bail!("No such key {:?} in {table_name:?}", val_to_string(k_ty, k));
             }
             drop(table);
-            w.commit()?;
+            crate::commit(w, args.dry_run)?;
             return Ok(());
         }
         (Some(k), None) => {
-            let k = string_to_val(k_ty, k)?;
+            let k = check_key(string_to_val(k_ty, k)?)?;
             let v = table.get(&k)?;
 
             if v.is_empty() {
                 bail!("No such key {:?} in {table_name:?}", val_to_string(k_ty, k));
             }
 
-            let k = val_to_string(k_ty, k);
-            // or out.entry(k).or_insert(v.unwrap().value());
-            // or #[cfg(false)]
-            let vs = out
-                .entry(k)
-                .or_insert(serde_json::Value::Array(Vec::new()))
-                .as_array_mut()
-                .unwrap();
-
+            out.begin_table(table_name, ty)?;
+            out.begin_entry(k)?;
+            // or out.entry_value(v.unwrap().value())?;
             // or #[cfg(false)]
             for v in v {
-                vs.push(v?.value());
+                out.entry_value(v?.value())?;
             }
+            out.end_entry()?;
+            out.end_table()?;
         }
         (Some(k), Some(v)) => {
-            let k = string_to_val(k_ty, k)?;
-            let v = string_to_val(v_ty, v)?;
+            let k = check_key(string_to_val(k_ty, k)?)?;
+            let v = check_value(string_to_val(v_ty, v)?)?;
 
             let w = db.begin_write()?;
             let mut table = w.open_multimap_table(table_def)?;
-            table.insert(k.clone(), v.clone())?;
+            insert_multimap(&mut table, &k, &v, data.changes.as_mut())?;
             drop(table);
-            w.commit()?;
+            crate::commit(w, args.dry_run)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the type names and widths of a table, kept in the `K_*` and `V_*` thread-locals
+pub fn resolve_multimap_types(args: &CliArgs, db: &DB, table_name: &str) -> Result<()> {
+    K_NAME.set(String::type_name());
+    K_WIDTH.set(None);
+    K_TREE.set(None);
+
+    V_NAME.set(String::type_name());
+    V_WIDTH.set(None);
+    V_TREE.set(None);
+
+    if let Some(schema) = &args.schema {
+        set_schema(schema)?;
+    }
+
+    let table_def = MultimapTableDefinition::<K, V>::new(table_name);
+    for _ in 0..5 {
+        match db.begin_read()?.open_multimap_table(table_def) {
+            Err(TableError::TableTypeMismatch { key, value, .. }) if args.schema.is_none() => {
+                K_NAME.set(key);
+                V_NAME.set(value)
+            }
+            Err(TableError::TypeDefinitionChanged { name, width, .. }) if args.schema.is_none() => {
+                if K_NAME.with_borrow(|n| n == &name) {
+                    K_WIDTH.set(width);
+                }
+                if V_NAME.with_borrow(|n| n == &name) {
+                    V_WIDTH.set(width);
+                }
+            }
+            Err(TableError::TableDoesNotExist(_)) if args.create => {
+                eprintln!("Creating table {table_name:?}");
+                let w = db.begin_write()?;
+                w.open_multimap_table(table_def)?;
+                w.commit()?;
+            }
+            Err(err) => return Err(err.into()),
+            Ok(_) => break,
         }
     }
 
     Ok(())
 }
+
+/// Parse the resolved types into `K_TREE` and `V_TREE`, returning the names keys and values
+/// given by the user are read as
+pub fn parse_multimap_types(args: &CliArgs, table_name: &str) -> KVType<&'static String> {
+    let k_ty = Box::leak(Box::new(String::new()));
+    let v_ty = Box::leak(Box::new(String::new()));
+
+    let k = K_NAME.with_borrow(|n| n.clone());
+    k_ty.push_str(k.name());
+
+    let v = V_NAME.with_borrow(|n| n.clone());
+    v_ty.push_str(v.name());
+
+    let tree = |name: &str, width: Option<usize>| {
+        let tree = parser::parse_tree(name);
+        match tree.and_then(|t| parser::check_width(&t, width).map(|_| t)) {
+            Ok(tree) if args.raw => tree.into_raw(),
+            Ok(tree) => tree,
+            Err(err) => {
+                eprintln!(
+                    "{WARNING} Error parsing type {name:?} of table {table_name:?}, showing raw bytes: {err}",
+                );
+                TypeDesc::Raw { ty: None, width }
+            }
+        }
+    };
+    let k_tree = tree(k_ty, K_WIDTH.get());
+    let v_tree = tree(v_ty, V_WIDTH.get());
+
+    // Raw bytes are read and written as bare hex strings
+    for (ty, tree) in [(&mut *k_ty, &k_tree), (&mut *v_ty, &v_tree)] {
+        if matches!(tree, TypeDesc::Raw { .. }) {
+            ty.clear();
+            ty.push_str("String");
+        }
+    }
+
+    K_TREE.set(Some(k_tree));
+    V_TREE.set(Some(v_tree));
+
+    KVType {
+        k_ty,
+        v_ty,
+        is_multi: true, // or is_multi: false,
+    }
+}
+
+/// Current value of a key, the values of multimap keys as an array
+// Normal tables return an `Option`
+#[allow(for_loops_over_fallibles)]
+fn get_multimap(
+    table: &impl ReadableMultimapTable<K, V>,
+    k: &serde_json::Value,
+) -> Result<Option<serde_json::Value>> {
+    let mut values = Vec::new();
+    for v in table.get(k)? {
+        values.push(v?.value()); // or values.push(v.value());
+    }
+    Ok((!values.is_empty()).then(|| values.into())) // or Ok(values.pop())
+}
+
+fn check_multimap(value: &serde_json::Value) -> Result<()> {
+    let values = value.as_array().map(Vec::as_slice); // or let values = Some(std::slice::from_ref(value));
+    let values = values.ok_or_eyre("Values of a multimap key must be an array")?;
+    // or #[cfg(false)]
+    if values.is_empty() {
+        bail!("No values left, use rm to remove the key");
+    }
+    for v in values {
+        check_value(v.clone())?;
+    }
+    Ok(())
+}
+
+/// Replace the value of a key, or all values of a multimap key
+fn put_multimap(
+    table: &mut MultimapTable<K, V>,
+    k: &serde_json::Value,
+    value: serde_json::Value,
+) -> Result<()> {
+    // or #[cfg(false)]
+    table.remove_all(k)?;
+    let values = value.as_array().cloned().unwrap_or_default(); // or let values = [value];
+    for v in values {
+        table.insert(k, v)?;
+    }
+    Ok(())
+}
+
+/// Insert an entry, reporting it to a dry run
+pub fn insert_multimap(
+    table: &mut MultimapTable<K, V>,
+    k: &serde_json::Value,
+    v: &serde_json::Value,
+    changes: Option<&mut Changes>,
+) -> Result<()> {
+    let old = table.insert(k, v)?;
+    let old = old.then(|| v.clone()); // or let old = old.map(|old| old.value());
+    if let Some(changes) = changes {
+        changes.insert(k, old, v)?;
+    }
+    Ok(())
+}
+
+/// Remove a key, reporting its values to a dry run, returns false if there was no such key
+#[allow(for_loops_over_fallibles)]
+pub fn remove_all_multimap(
+    table: &mut MultimapTable<K, V>,
+    k: &serde_json::Value,
+    mut changes: Option<&mut Changes>,
+) -> Result<bool> {
+    let mut found = false;
+    let removed = table.remove_all(k)?; // or let removed = table.remove(k)?;
+    for v in removed {
+        let v = v?.value(); // or let v = v.value();
+        if let Some(changes) = changes.as_deref_mut() {
+            changes.remove(k, &v)?;
+        }
+        found = true;
+    }
+    Ok(found)
+}
@@ -0,0 +1,496 @@
+//! Interactive shell, keeping the database open and the types of each table resolved once
+
+use clap::{CommandFactory, Parser};
+use eyre::{OptionExt, Result, bail};
+use redb::{
+    AccessGuard, MultimapTableDefinition, MultimapTableHandle, ReadableMultimapTable,
+    ReadableTable, StorageError, TableDefinition, TableHandle, WriteTransaction,
+};
+use rustyline::{
+    Context, Editor, Helper,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write},
+    ops::Bound,
+    path::PathBuf,
+};
+
+use crate::{
+    DB, Data, KVType, Schema, WARNING,
+    cli::{CliArgs, FilterArgs, Shell, ShellCommand},
+    parser,
+    print::{self, Printer},
+    transcode::{
+        K, K_NAME, K_TREE, V, V_NAME, V_TREE, check_key, check_value, string_to_val, val_to_string,
+    },
+};
+
+/// Keys offered at once by tab completion
+const MAX_COMPLETIONS: usize = 100;
+/// Keys read by tab completion, since only string keys with a prefix are found in a row
+const MAX_SCANNED: usize = 10_000;
+
+pub fn run(args: &CliArgs, db: DB) -> Result<()> {
+    let mut rl = Editor::<Session, DefaultHistory>::new()?;
+    rl.set_helper(Some(Session::new(args.clone(), db)));
+
+    let history =
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".redb-cli_history"));
+    if let Some(history) = &history {
+        // There's no history before the first run
+        rl.load_history(history).ok();
+    }
+
+    loop {
+        let prompt = rl.helper().unwrap().prompt();
+        let line = match rl.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        rl.add_history_entry(line.as_str())?;
+        match rl.helper_mut().unwrap().exec(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("Error: {err:#}"),
+        }
+    }
+
+    if let Some(history) = &history
+        && let Err(err) = rl.save_history(history)
+    {
+        eprintln!("{WARNING} Can't save history to {history:?}: {err}");
+    }
+    if let Some(w) = rl.helper_mut().unwrap().txn.take() {
+        w.abort()?;
+        eprintln!("Rolled back the open transaction");
+    }
+
+    Ok(())
+}
+
+/// Open a table in the open transaction, to see its changes, or in a new read transaction
+macro_rules! open {
+    ($self:ident.$open:ident($def:expr), |$table:ident| $body:expr) => {
+        match &$self.txn {
+            Some(w) => {
+                let $table = w.$open($def)?;
+                $body
+            }
+            None => {
+                let r = $self.db.begin_read()?;
+                let $table = r.$open($def)?;
+                $body
+            }
+        }
+    };
+}
+
+pub struct Session {
+    args: CliArgs,
+    db: DB,
+    schemas: BTreeMap<String, Schema>,
+    /// Table chosen with `use`
+    table: Option<String>,
+    /// Transaction started with `begin`, changes are committed right away without one
+    txn: Option<WriteTransaction>,
+    /// Output of each command that prints entries
+    out: Box<dyn Fn() -> Box<dyn Write>>,
+}
+
+impl Session {
+    pub fn new(args: CliArgs, db: DB) -> Self {
+        Self::with_writer(args, db, || Box::new(BufWriter::new(std::io::stdout())))
+    }
+
+    pub fn with_writer(args: CliArgs, db: DB, out: impl Fn() -> Box<dyn Write> + 'static) -> Self {
+        Self {
+            args,
+            db,
+            schemas: BTreeMap::new(),
+            table: None,
+            txn: None,
+            out: Box::new(out),
+        }
+    }
+
+    fn prompt(&self) -> String {
+        let table = self.table.as_deref().unwrap_or("");
+        let txn = if self.txn.is_some() { "*" } else { "" };
+        format!("{table}{txn}> ")
+    }
+
+    /// Run a line of input, returns false once the shell should exit
+    pub fn exec(&mut self, line: &str) -> Result<bool> {
+        let words = shlex::split(line).ok_or_eyre("Unbalanced quotes")?;
+        if words.is_empty() {
+            return Ok(true);
+        }
+        let command = match Shell::try_parse_from(words) {
+            Ok(shell) => shell.command,
+            // Also prints help
+            Err(err) => {
+                err.print()?;
+                return Ok(true);
+            }
+        };
+
+        match command {
+            ShellCommand::Use { table } => {
+                self.enter(&table)?;
+                self.table = Some(table);
+            }
+            ShellCommand::Get { key } => self.get(&key)?,
+            ShellCommand::Put { key, value } => self.put(&key, &value)?,
+            ShellCommand::Rm { key, value } => self.remove(&key, value.as_deref())?,
+            ShellCommand::Range { from, to, filter } => self.range(from, to, filter)?,
+            ShellCommand::Ls => self.list()?,
+            ShellCommand::Begin => {
+                if self.txn.is_some() {
+                    bail!("A transaction is already open");
+                }
+                self.txn = Some(self.db.begin_write()?);
+            }
+            ShellCommand::Commit => self
+                .txn
+                .take()
+                .ok_or_eyre("No open transaction")?
+                .commit()?,
+            ShellCommand::Rollback => self.txn.take().ok_or_eyre("No open transaction")?.abort()?,
+            ShellCommand::Exit => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Resolve the types of a table the first time it's used
+    fn enter(&mut self, table: &str) -> Result<KVType<&'static String>> {
+        if let Some(schema) = self.schemas.get(table) {
+            return Ok(schema.enter());
+        }
+
+        // Creating a table waits for the open transaction to finish
+        let args = CliArgs {
+            create: self.args.create && self.txn.is_none(),
+            ..self.args.clone()
+        };
+        let schema = Schema::resolve(&args, &self.db, table)?;
+        let ty = schema.enter();
+        self.schemas.insert(table.to_string(), schema);
+        Ok(ty)
+    }
+
+    fn current(&mut self) -> Result<(String, KVType<&'static String>)> {
+        let table = self
+            .table
+            .clone()
+            .ok_or_eyre("No table chosen, see `use`")?;
+        let ty = self.enter(&table)?;
+        Ok((table, ty))
+    }
+
+    /// Entries of unknown types can still be listed, but not looked up
+    fn check_order(&self, table: &str) -> Result<()> {
+        if !K_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Key type {:?} of table {table:?} can't be ordered",
+                K_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        Ok(())
+    }
+
+    fn key(&self, table: &str, ty: KVType<&'static String>, key: &str) -> Result<Value> {
+        self.check_order(table)?;
+        check_key(string_to_val(ty.k_ty, key)?)
+    }
+
+    fn value(&self, table: &str, ty: KVType<&'static String>, value: &str) -> Result<Value> {
+        if ty.is_multi && !V_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!(
+                "Value type {:?} of table {table:?} can't be ordered",
+                V_NAME.with_borrow(|n| n.name().to_string()),
+            );
+        }
+        check_value(string_to_val(ty.v_ty, value)?)
+    }
+
+    fn printer(&self, table: &str, key: Option<&str>, filter: FilterArgs) -> Result<Printer> {
+        let args = CliArgs {
+            table: Some(table.to_string()),
+            key: key.map(Into::into),
+            filter: filter.filter,
+            fields: filter.fields,
+            ..self.args.clone()
+        };
+        Printer::with_writer(&args, (self.out)())
+    }
+
+    /// Run a change in the open transaction, or commit it right away
+    fn write(&self, f: impl FnOnce(&WriteTransaction) -> Result<()>) -> Result<()> {
+        if let Some(w) = &self.txn {
+            return f(w);
+        }
+        let w = self.db.begin_write()?;
+        f(&w)?;
+        w.commit()?;
+        Ok(())
+    }
+
+    fn get(&mut self, key: &str) -> Result<()> {
+        let (table, ty) = self.current()?;
+        let k = self.key(&table, ty, key)?;
+
+        let values = if ty.is_multi {
+            let def = MultimapTableDefinition::<K, V>::new(&table);
+            open!(self.open_multimap_table(def), |t| {
+                t.get(&k)?
+                    .map(|v| Ok(v?.value()))
+                    .collect::<Result<Vec<_>>>()?
+            })
+        } else {
+            let def = TableDefinition::<K, V>::new(&table);
+            open!(self.open_table(def), |t| {
+                t.get(&k)?.map(|v| v.value()).into_iter().collect()
+            })
+        };
+        if values.is_empty() {
+            bail!("No such key {:?} in {table:?}", val_to_string(ty.k_ty, k));
+        }
+
+        let mut out = self.printer(&table, Some(key), FilterArgs::default())?;
+        out.begin_table(&table, ty)?;
+        out.begin_entry(k)?;
+        for v in values {
+            out.entry_value(v)?;
+        }
+        out.end_entry()?;
+        out.end_table()?;
+        out.finish()
+    }
+
+    fn put(&mut self, key: &str, value: &str) -> Result<()> {
+        let (table, ty) = self.current()?;
+        let k = self.key(&table, ty, key)?;
+        let v = self.value(&table, ty, value)?;
+
+        self.write(|w| {
+            if ty.is_multi {
+                w.open_multimap_table(MultimapTableDefinition::<K, V>::new(&table))?
+                    .insert(k, v)?;
+            } else {
+                w.open_table(TableDefinition::<K, V>::new(&table))?
+                    .insert(k, v)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        let (table, ty) = self.current()?;
+        let k = self.key(&table, ty, key)?;
+        let v = value.map(|v| self.value(&table, ty, v)).transpose()?;
+        if v.is_some() && !ty.is_multi {
+            bail!("Only values of multimap tables can be removed one by one");
+        }
+
+        self.write(|w| {
+            let found = if ty.is_multi {
+                let mut t = w.open_multimap_table(MultimapTableDefinition::<K, V>::new(&table))?;
+                match &v {
+                    Some(v) => t.remove(&k, v)?,
+                    None => !t.remove_all(&k)?.is_empty(),
+                }
+            } else {
+                let mut t = w.open_table(TableDefinition::<K, V>::new(&table))?;
+                t.remove(&k)?.is_some()
+            };
+            if !found {
+                bail!("No such key {:?} in {table:?}", val_to_string(ty.k_ty, k));
+            }
+            Ok(())
+        })
+    }
+
+    fn range(
+        &mut self,
+        from: Option<String>,
+        to: Option<String>,
+        filter: FilterArgs,
+    ) -> Result<()> {
+        let (table, ty) = self.current()?;
+        if from.is_some() || to.is_some() {
+            self.check_order(&table)?;
+        }
+        let bound = |k: Option<String>| -> Result<Option<Value>> {
+            let Some(k) = k else { return Ok(None) };
+            let k = string_to_val(ty.k_ty, &k)?;
+            let k = K_TREE.with_borrow(|t| parser::pad_prefix(t.as_ref().unwrap(), k))?;
+            Ok(Some(check_key(k)?))
+        };
+        let from = bound(from)?.map_or(Bound::Unbounded, Bound::Included);
+        let to = bound(to)?.map_or(Bound::Unbounded, Bound::Excluded);
+
+        let mut out = self.printer(&table, None, filter)?;
+        out.begin_table(&table, ty)?;
+        if ty.is_multi {
+            let def = MultimapTableDefinition::<K, V>::new(&table);
+            open!(self.open_multimap_table(def), |t| {
+                for r in t.range::<Value>((from, to))? {
+                    let (k, values) = r?;
+                    out.begin_entry(k.value())?;
+                    for v in values {
+                        out.entry_value(v?.value())?;
+                    }
+                    out.end_entry()?;
+                }
+            })
+        } else {
+            let def = TableDefinition::<K, V>::new(&table);
+            open!(self.open_table(def), |t| {
+                for r in t.range::<Value>((from, to))? {
+                    let (k, v) = r?;
+                    out.begin_entry(k.value())?;
+                    out.entry_value(v.value())?;
+                    out.end_entry()?;
+                }
+            })
+        }
+        out.end_table()?;
+        out.finish()
+    }
+
+    fn list(&mut self) -> Result<()> {
+        let mut data = Data::default();
+        for table in self.tables()? {
+            // Tables of unknown types are still listed by the types they're stored with
+            if let Err(err) = self.enter(&table) {
+                eprintln!("{WARNING} Can't open table {table:?}: {err}");
+                continue;
+            }
+            let types = self.schemas[&table].names().map(String::from);
+            data.list.insert(table, types.into());
+        }
+        let args = CliArgs {
+            list: true,
+            ..self.args.clone()
+        };
+        print::print(&args, data)
+    }
+
+    fn tables(&self) -> Result<Vec<String>> {
+        let r = self.db.begin_read()?;
+        let mut tables: Vec<_> = r.list_tables()?.map(|t| t.name().to_string()).collect();
+        tables.extend(r.list_multimap_tables()?.map(|t| t.name().to_string()));
+        tables.sort();
+        Ok(tables)
+    }
+
+    /// Keys of the current table starting with a prefix, as they're typed
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let Some(schema) = self.table.as_ref().and_then(|t| self.schemas.get(t)) else {
+            return Ok(Vec::new());
+        };
+        let table = self.table.as_deref().unwrap();
+        let ty = schema.enter();
+        if !K_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            return Ok(Vec::new());
+        }
+
+        // Strings are ordered by their bytes, so the keys with the prefix come in a row
+        let is_str = !self.args.raw && matches!(ty.k_ty.as_str(), "&str" | "String");
+        let from = match is_str {
+            true => Bound::Included(Value::String(prefix.to_string())),
+            false => Bound::Unbounded,
+        };
+        let range = (from, Bound::Unbounded);
+
+        if ty.is_multi {
+            let def = MultimapTableDefinition::<K, V>::new(table);
+            open!(self.open_multimap_table(def), |t| {
+                matching_keys(t.range::<Value>(range)?, ty.k_ty, prefix, is_str)
+            })
+        } else {
+            let def = TableDefinition::<K, V>::new(table);
+            open!(self.open_table(def), |t| {
+                matching_keys(t.range::<Value>(range)?, ty.k_ty, prefix, is_str)
+            })
+        }
+    }
+}
+
+fn matching_keys<'a, T>(
+    entries: impl Iterator<Item = Result<(AccessGuard<'a, K>, T), StorageError>>,
+    k_ty: &'static str,
+    prefix: &str,
+    is_str: bool,
+) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for entry in entries.take(MAX_SCANNED) {
+        let k = val_to_string(k_ty, entry?.0.value());
+        if k.starts_with(prefix) {
+            keys.push(k);
+        } else if is_str {
+            break;
+        }
+        if keys.len() == MAX_COMPLETIONS {
+            break;
+        }
+    }
+    Ok(keys)
+}
+
+impl Completer for Session {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words: Vec<_> = line[..start].split_whitespace().collect();
+        let word = line[start..].trim_start_matches(['"', '\'']);
+
+        let candidates = match words.as_slice() {
+            [] => Shell::command()
+                .get_subcommands()
+                .map(|c| c.get_name().to_string())
+                .collect(),
+            ["use"] => self.tables().unwrap_or_default(),
+            ["get" | "put" | "rm"] => self.keys(word).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let candidates = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair {
+                replacement: shlex::try_quote(&c).map_or_else(|_| c.clone(), Into::into),
+                display: c,
+            })
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Session {
+    type Hint = String;
+}
+
+impl Highlighter for Session {}
+
+impl Validator for Session {}
+
+impl Helper for Session {}
@@ -0,0 +1,110 @@
+use redb::{
+    Database, ReadableTableMetadata, TableDefinition, TableHandle, backends::InMemoryBackend,
+};
+
+use crate::{DB, batch, cli::CliArgs, utils::Buf};
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+
+fn run(db: &DB, ops: &str) -> eyre::Result<()> {
+    let out = run_with(&CliArgs::default(), db, ops)?;
+    assert_eq!(out, "");
+    Ok(())
+}
+
+/// Changes printed by the batch
+fn run_with(args: &CliArgs, db: &DB, ops: &str) -> eyre::Result<String> {
+    let path = std::env::temp_dir().join(format!("redb-cli-batch-{}.ndjson", std::process::id()));
+    std::fs::write(&path, ops).unwrap();
+    let buf = Buf::default();
+    let res = batch::run_with_writer(args, db, path.to_str().unwrap(), Box::new(buf.clone()));
+    std::fs::remove_file(&path).unwrap();
+    res.map(|()| buf.take())
+}
+
+#[test]
+fn test_batch() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let w = db.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(1, "alice").unwrap();
+    users.insert(2, "bob").unwrap();
+    drop(users);
+    w.commit().unwrap();
+    let db = DB::RW(db);
+
+    run(
+        &db,
+        r#"{"op": "create-table", "table": "by_name", "schema": "&str -> u64"}
+{"op": "put", "table": "by_name", "key": "alice", "value": 1}
+{"op": "create-table", "table": "tags", "schema": "u64 -> &str", "multimap": true}
+{"op": "put", "table": "tags", "key": 1, "value": "admin"}
+{"op": "put", "table": "tags", "key": 1, "value": "dev"}
+{"op": "remove", "table": "tags", "key": 1, "value": "dev"}
+
+{"op": "remove", "table": "users", "key": 2}
+"#,
+    )
+    .unwrap();
+
+    let r = db.begin_read().unwrap();
+    let users = r.open_table(USERS).unwrap();
+    assert_eq!(users.len().unwrap(), 1);
+    drop(r);
+
+    // Nothing is written when a line fails
+    for ops in [
+        r#"{"op": "put", "table": "users", "key": 3, "value": "carol"}
+{"op": "remove", "table": "users", "key": 9}"#,
+        r#"{"op": "delete-table", "table": "by_name"}
+{"op": "put", "table": "by_name", "key": "bob", "value": 2}"#,
+        r#"{"op": "put", "table": "users", "key": 3, "value": "carol"}
+{"op": "put", "table": "users", "key": "x", "value": "dave"}"#,
+        r#"{"op": "create-table", "table": "users", "schema": "u64 -> u64"}"#,
+        r#"{"op": "put", "table": "users", "key": 3, "value": "carol", "extra": 1}"#,
+    ] {
+        let err = run(&db, ops).unwrap_err();
+        let line = ops.lines().count();
+        assert_eq!(err.to_string(), format!("Batch aborted at line {line}"));
+    }
+
+    // Dry runs are rolled back even if every line succeeds
+    let args = CliArgs {
+        dry_run: true,
+        ..Default::default()
+    };
+    let out = run_with(
+        &args,
+        &db,
+        r#"{"op": "put", "table": "users", "key": 3, "value": "carol"}
+{"op": "delete-table", "table": "tags"}"#,
+    )
+    .unwrap();
+    assert_eq!(out, "users:\n+ 3: carol\n- table tags\n");
+
+    let r = db.begin_read().unwrap();
+    assert_eq!(r.open_table(USERS).unwrap().len().unwrap(), 1);
+    let tables: Vec<_> = r
+        .list_tables()
+        .unwrap()
+        .map(|t| t.name().to_string())
+        .collect();
+    assert_eq!(tables, ["by_name", "users"]);
+    assert_eq!(r.list_multimap_tables().unwrap().count(), 1);
+    drop(r);
+
+    // Tables created by a batch are opened with the types they were created with
+    run(
+        &db,
+        r#"{"op": "remove", "table": "by_name", "key": "alice"}
+{"op": "remove", "table": "tags", "key": 1, "value": "admin"}"#,
+    )
+    .unwrap();
+    let err = run(&db, r#"{"op": "remove", "table": "tags", "key": 1}"#).unwrap_err();
+    assert_eq!(
+        format!("{:#}", err),
+        "Batch aborted at line 1: No such key \"1\" in \"tags\""
+    );
+}
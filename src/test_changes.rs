@@ -0,0 +1,82 @@
+use redb::{Database, ReadableTable, TableDefinition, backends::InMemoryBackend};
+
+use crate::{DB, Data, changes::Changes, cli::CliArgs, print::Format, process, utils::Buf};
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+
+fn dry_run(db: &DB, format: Format, args: CliArgs) -> String {
+    let buf = Buf::default();
+    let mut data = Data {
+        changes: Some(Changes::with_writer(format, Box::new(buf.clone()))),
+        ..Default::default()
+    };
+    let args = CliArgs {
+        table: Some("users".into()),
+        dry_run: true,
+        ..args
+    };
+    process::process(&args, db, &mut data).unwrap();
+    data.changes.unwrap().finish().unwrap();
+    buf.take()
+}
+
+#[test]
+fn test_dry_run() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let w = db.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(5, "alice").unwrap();
+    users.insert(6, "bob/smith").unwrap();
+    drop(users);
+    w.commit().unwrap();
+    let db = DB::RW(db);
+
+    let put = |key: &str, value: &str| CliArgs {
+        key: Some(key.into()),
+        value: Some(value.into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        dry_run(&db, Format::Text, put("5", "mallory")),
+        "users:\n~ 5: alice -> mallory\n"
+    );
+    assert_eq!(
+        dry_run(&db, Format::Text, put("7", "carol")),
+        "users:\n+ 7: carol\n"
+    );
+    assert_eq!(dry_run(&db, Format::Text, put("5", "alice")), "");
+
+    let remove = CliArgs {
+        from: Some("6".into()),
+        remove: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        dry_run(&db, Format::Json, remove),
+        "[\n  {\"op\":\"remove\",\"path\":\"/users/6\",\"old\":\"bob/smith\"}\n]\n"
+    );
+    let remove = CliArgs {
+        key: Some("5".into()),
+        remove: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        dry_run(&db, Format::Ndjson, remove),
+        "{\"op\":\"remove\",\"path\":\"/users/5\",\"old\":\"alice\"}\n"
+    );
+
+    // Nothing was written
+    let r = db.begin_read().unwrap();
+    let users = r.open_table(USERS).unwrap();
+    let entries: Vec<_> = users
+        .iter()
+        .unwrap()
+        .map(|e| {
+            let (k, v) = e.unwrap();
+            (k.value(), v.value().to_string())
+        })
+        .collect();
+    assert_eq!(entries, [(5, "alice".into()), (6, "bob/smith".into())]);
+}
@@ -0,0 +1,148 @@
+use clap::Parser;
+
+use crate::{
+    cli::{Cli, CliArgs, Conflict, Savepoint, hoist_subcommand},
+    print::Format,
+};
+
+fn parse(args: &str) -> clap::error::Result<CliArgs> {
+    let args = std::iter::once("redb-cli").chain(args.split(' '));
+    let args = hoist_subcommand(args.map(Into::into).collect());
+    Ok(Cli::try_parse_from(args)?.into_args())
+}
+
+#[test]
+fn test_subcommands() {
+    let args = parse("get db users 5").unwrap();
+    assert_eq!(args.file, "db");
+    assert_eq!(args.table.as_deref(), Some("users"));
+    assert_eq!(args.key.as_deref(), Some("5"));
+    assert!(args.value.is_none() && !args.remove);
+
+    let args = parse("rm -m db users 5 alice").unwrap();
+    assert!(args.remove && args.multimap);
+    assert_eq!(args.value.as_deref(), Some("alice"));
+
+    let args = parse("get db users --from 5 --to 7").unwrap();
+    assert_eq!(args.from.as_deref(), Some("5"));
+    assert_eq!(args.to.as_deref(), Some("7"));
+    assert!(parse("get db users 5 --from 5").is_err());
+
+    let args = parse("get db users --filter .value.active --fields name,address.city").unwrap();
+    assert_eq!(args.filter.as_deref(), Some(".value.active"));
+    assert_eq!(args.fields, ["name", "address.city"]);
+
+    // Options before the subcommand
+    let args = parse("-j --schema u64->String drop db users").unwrap();
+    assert!(args.format == Format::Json && args.delete);
+    assert_eq!(args.schema.as_deref(), Some("u64->String"));
+
+    let args = parse("edit -m db users 5").unwrap();
+    assert!(args.edit && args.multimap);
+    assert_eq!(args.key.as_deref(), Some("5"));
+    assert!(parse("--edit db users 5 alice").is_err());
+
+    let args = parse("-j savepoint restore db 3").unwrap();
+    assert_eq!(args.savepoint, Some(Savepoint::Restore(3)));
+    assert_eq!(args.file, "db");
+    assert!(parse("savepoint rm db").is_err());
+
+    let args = parse("diff old.db:users new.db").unwrap();
+    let [left, right] = args.diff.unwrap();
+    assert_eq!(
+        (left.file.as_str(), left.table.as_deref()),
+        ("old.db", Some("users"))
+    );
+    assert_eq!((right.file.as_str(), right.table), ("new.db", None));
+    assert_eq!(args.file, "old.db");
+
+    let copy = parse("copy src.db:users dst.db --on-conflict skip")
+        .unwrap()
+        .copy
+        .unwrap();
+    assert_eq!(copy.src.table.as_deref(), Some("users"));
+    assert_eq!(copy.on_conflict, Conflict::Skip);
+
+    assert!(parse("put db users 5").is_err());
+    assert!(parse("-r get db users 5").is_err());
+    assert!(parse("ls").is_err());
+}
+
+#[test]
+fn test_shorthand() {
+    let args = parse("db users 5 alice").unwrap();
+    assert_eq!(args.key.as_deref(), Some("5"));
+    assert_eq!(args.value.as_deref(), Some("alice"));
+
+    let args = parse("-r db users 5").unwrap();
+    assert!(args.remove);
+    assert!(args.value.is_none());
+
+    // Tables may be named like subcommands
+    let args = parse("-j db get").unwrap();
+    assert_eq!(args.format, Format::Json);
+    assert_eq!(args.file, "db");
+    assert_eq!(args.table.as_deref(), Some("get"));
+
+    let args = parse("--stats db").unwrap();
+    assert!(args.stats && args.table.is_none());
+}
+
+#[test]
+fn test_load() {
+    let args = parse("load db users in.csv --column id=key --commit-every 100").unwrap();
+    let load = args.load.unwrap();
+    assert_eq!(load.input.as_deref(), Some("in.csv"));
+    assert_eq!(load.columns, ["id=key"]);
+    assert_eq!(load.commit_every.map(|n| n.get()), Some(100));
+
+    assert!(parse("load db users --commit-every 0").is_err());
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(parse("get db users").unwrap().format, Format::Text);
+    assert_eq!(
+        parse("get db users --ndjson").unwrap().format,
+        Format::Ndjson
+    );
+    assert_eq!(
+        parse("--format csv get db users").unwrap().format,
+        Format::Csv
+    );
+    assert_eq!(
+        parse("db users --format yaml").unwrap().format,
+        Format::Yaml
+    );
+
+    assert!(parse("get db users --format dump").is_err());
+    assert!(parse("get db users -j --format tsv").is_err());
+}
+
+#[test]
+fn test_bulk_remove() {
+    let args = parse("rm db users --from 5 --dry-run").unwrap();
+    assert!(args.remove && args.dry_run && args.key.is_none());
+    assert_eq!(args.from.as_deref(), Some("5"));
+
+    let args = parse("-r db users --filter .value.active").unwrap();
+    assert!(args.remove && !args.dry_run);
+    assert_eq!(args.filter.as_deref(), Some(".value.active"));
+
+    assert!(parse("rm db users").is_err());
+    assert!(parse("-r db users").is_err());
+    // A filter doesn't remove entries of every table
+    assert!(parse("-r db --filter .value.active").is_err());
+    assert!(parse("--dry-run -r db --filter .value.active").is_err());
+    assert!(parse("--dry-run db users 5").is_err());
+}
+
+#[test]
+fn test_dry_run() {
+    assert!(parse("put db users 5 alice --dry-run").unwrap().dry_run);
+    assert!(parse("load db users in.ndjson --dry-run").unwrap().dry_run);
+    assert!(parse("batch db --dry-run").unwrap().dry_run);
+    assert!(parse("--dry-run db users 5 alice").unwrap().dry_run);
+    assert!(parse("db --batch ops.ndjson --dry-run").unwrap().dry_run);
+    assert!(parse("-l db --dry-run").is_err());
+}
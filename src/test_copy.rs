@@ -0,0 +1,102 @@
+use redb::{
+    Database, MultimapTableDefinition, ReadableDatabase, ReadableTableMetadata, TableDefinition,
+};
+
+use crate::{
+    cli::{CliArgs, Conflict, CopyArgs, Location},
+    copy,
+};
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+const PEOPLE: TableDefinition<u64, &str> = TableDefinition::new("people");
+const TAGS: MultimapTableDefinition<u64, &str> = MultimapTableDefinition::new("tags");
+
+fn run(src: &str, dst: &str, on_conflict: Conflict) -> eyre::Result<()> {
+    let copy = CopyArgs {
+        src: src.parse().unwrap(),
+        dst: dst.parse().unwrap(),
+        on_conflict,
+    };
+    copy::run(&CliArgs::default(), &copy)
+}
+
+#[test]
+fn test_copy() {
+    let dir = std::env::temp_dir();
+    let path = |name: &str| {
+        let path = dir.join(format!("redb-cli-copy-{}-{name}.redb", std::process::id()));
+        path.to_str().unwrap().to_string()
+    };
+    let (src, dst) = (path("src"), path("dst"));
+    let _ = std::fs::remove_file(&dst);
+
+    let db = Database::create(&src).unwrap();
+    let w = db.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(1, "alice").unwrap();
+    users.insert(2, "bob").unwrap();
+    let mut tags = w.open_multimap_table(TAGS).unwrap();
+    tags.insert(1, "admin").unwrap();
+    tags.insert(1, "dev").unwrap();
+    drop((users, tags));
+    w.commit().unwrap();
+    drop(db);
+
+    // Every table, opened in the destination with the definitions of the source
+    run(&src, &dst, Conflict::Fail).unwrap();
+    let get = |table: TableDefinition<u64, &str>, key| {
+        let db = Database::open(&dst).unwrap();
+        let r = db.begin_read().unwrap();
+        let table = r.open_table(table).unwrap();
+        table.get(key).unwrap().map(|v| v.value().to_string())
+    };
+    assert_eq!(get(USERS, 2).as_deref(), Some("bob"));
+    let db = Database::open(&dst).unwrap();
+    let r = db.begin_read().unwrap();
+    assert_eq!(r.open_multimap_table(TAGS).unwrap().len().unwrap(), 2);
+    drop((r, db));
+
+    let db = Database::open(&src).unwrap();
+    let w = db.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(2, "robert").unwrap();
+    users.insert(3, "carol").unwrap();
+    drop(users);
+    w.commit().unwrap();
+    drop(db);
+
+    let table = |db: &str, table: &str| format!("{db}:{table}");
+    let err = run(&table(&src, "users"), &dst, Conflict::Fail).unwrap_err();
+    assert_eq!(err.to_string(), "Key \"1\" already exists in \"users\"");
+    assert_eq!(get(USERS, 3), None);
+
+    run(&table(&src, "users"), &dst, Conflict::Skip).unwrap();
+    assert_eq!(get(USERS, 2).as_deref(), Some("bob"));
+    assert_eq!(get(USERS, 3).as_deref(), Some("carol"));
+
+    run(&table(&src, "users"), &dst, Conflict::Overwrite).unwrap();
+    assert_eq!(get(USERS, 2).as_deref(), Some("robert"));
+
+    // Another name, in the same file
+    run(
+        &table(&dst, "users"),
+        &table(&dst, "people"),
+        Conflict::Fail,
+    )
+    .unwrap();
+    assert_eq!(get(PEOPLE, 1).as_deref(), Some("alice"));
+    let err = run(&table(&dst, "users"), &dst, Conflict::Fail).unwrap_err();
+    assert_eq!(err.to_string(), "Can't copy \"users\" onto itself");
+
+    // Tables with other types aren't mixed
+    let err = run(&table(&src, "tags"), &table(&dst, "users"), Conflict::Skip).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Can't open \"users\" with the types of \"tags\""
+    );
+
+    let location: Location = table(&src, "users").parse().unwrap();
+    assert_eq!(location.file, src);
+    std::fs::remove_file(&src).unwrap();
+    std::fs::remove_file(&dst).unwrap();
+}
@@ -0,0 +1,112 @@
+use redb::{Database, MultimapTableDefinition, TableDefinition, backends::InMemoryBackend};
+use serde_json::{Value, json};
+
+use crate::{DB, changes::Changes, cli::CliArgs, diff, print::Format, utils::Buf};
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+const TAGS: MultimapTableDefinition<u64, &str> = MultimapTableDefinition::new("tags");
+
+fn diff(format: Format, tables: [(&DB, Option<&str>); 2]) -> String {
+    let buf = Buf::default();
+    let mut changes = Changes::with_writer(format, Box::new(buf.clone()));
+    diff::diff(&CliArgs::default(), tables, &mut changes).unwrap();
+    changes.finish().unwrap();
+    buf.take()
+}
+
+#[test]
+fn test_diff() {
+    let new_db = || {
+        Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .unwrap()
+    };
+
+    let old = new_db();
+    let w = old.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(1, "alice").unwrap();
+    users.insert(2, "bob").unwrap();
+    users.insert(3, "carol").unwrap();
+    let mut tags = w.open_multimap_table(TAGS).unwrap();
+    tags.insert(1, "a").unwrap();
+    tags.insert(1, "b").unwrap();
+    let mut scores = w
+        .open_table(TableDefinition::<u64, u64>::new("scores"))
+        .unwrap();
+    scores.insert(1, 10).unwrap();
+    w.open_table(TableDefinition::<u64, u64>::new("old"))
+        .unwrap();
+    drop((users, tags, scores));
+    w.commit().unwrap();
+
+    let new = new_db();
+    let w = new.begin_write().unwrap();
+    let mut users = w.open_table(USERS).unwrap();
+    users.insert(1, "alice").unwrap();
+    users.insert(2, "robert").unwrap();
+    users.insert(4, "dave").unwrap();
+    let mut tags = w.open_multimap_table(TAGS).unwrap();
+    tags.insert(1, "b").unwrap();
+    tags.insert(1, "c").unwrap();
+    let mut scores = w
+        .open_table(TableDefinition::<u64, u32>::new("scores"))
+        .unwrap();
+    scores.insert(1, 10).unwrap();
+    scores.insert(2, 5).unwrap();
+    w.open_table(TableDefinition::<&str, u64>::new("new"))
+        .unwrap();
+    drop((users, tags, scores));
+    w.commit().unwrap();
+
+    let (old, new) = (DB::RW(old), DB::RW(new));
+    assert_eq!(
+        diff(Format::Text, [(&old, None), (&new, None)]),
+        "+ table new: &str -> u64
+- table old
+~ table scores: u64 -> u64 => u64 -> u32
+scores:
++ 2: 5
+tags:
+- 1: a
++ 1: c
+users:
+~ 2: bob -> robert
+- 3: carol
++ 4: dave
+"
+    );
+    assert_eq!(
+        diff(Format::Ndjson, [(&old, Some("users")), (&new, None)]),
+        r#"{"op":"replace","path":"/users/2","value":"robert","old":"bob"}
+{"op":"remove","path":"/users/3","old":"carol"}
+{"op":"add","path":"/users/4","value":"dave"}
+"#
+    );
+    let types = |value: &str, width: usize| {
+        let schema = |name, width| json!({"name": name, "class": "internal", "width": width});
+        json!({"multimap": false, "key": schema("u64", 8), "value": schema(value, width)})
+    };
+    let patch = diff(Format::Ndjson, [(&old, Some("scores")), (&new, None)]);
+    let patch: Vec<Value> = patch
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        patch,
+        [
+            json!({"op": "retype", "path": "/scores", "value": types("u32", 4), "old": types("u64", 8)}),
+            json!({"op": "add", "path": "/scores/2", "value": 5}),
+        ]
+    );
+
+    // Tables of the same database, reported under the name of the old one
+    assert_eq!(
+        diff(Format::Text, [(&old, Some("scores")), (&old, Some("old"))]),
+        "scores:\n- 1: 10\n"
+    );
+    assert_eq!(
+        diff(Format::Json, [(&old, Some("users")), (&old, Some("users"))]),
+        "[]\n"
+    );
+}
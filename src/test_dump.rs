@@ -0,0 +1,46 @@
+use redb::{Database, TableDefinition};
+
+use crate::{DB, dump};
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+
+#[test]
+fn test_restore() {
+    let dir = std::env::temp_dir();
+    let path = |name: &str| dir.join(format!("redb-cli-restore-{}-{name}", std::process::id()));
+    let (db_path, dump_path) = (path("db.redb"), path("dump.jsonl"));
+    let db = DB::RW(Database::create(&db_path).unwrap());
+    let restore = |lines: &[&str]| {
+        std::fs::write(&dump_path, lines.join("\n")).unwrap();
+        dump::restore(&db, dump_path.to_str()).map_err(|e| format!("{e:#}"))
+    };
+
+    let users = r#"{"table":"users","multimap":false,"key":{"name":"u64","class":"internal","width":8},"value":{"name":"&str","class":"internal","width":null}}"#;
+    restore(&[users, r#"{"key":1,"value":"alice"}"#]).unwrap();
+    let r = db.begin_read().unwrap();
+    let value = r.open_table(USERS).unwrap().get(1).unwrap();
+    assert_eq!(value.unwrap().value(), "alice");
+    drop(r);
+
+    // Entries are checked once their table is known
+    assert_eq!(
+        restore(&[r#"{"key":1,"value":"alice"}"#]).unwrap_err(),
+        "Error restoring line 1: Entry before the first table"
+    );
+
+    // Tables whose entries can't be inserted in order are refused
+    let opaque = r#"{"table":"opaque","multimap":false,"key":{"name":"Opaque","class":"user","width":null,"raw":true},"value":{"name":"&str","class":"internal","width":null}}"#;
+    assert_eq!(
+        restore(&[opaque, r#"{"key":"00","value":"x"}"#]).unwrap_err(),
+        "Error restoring line 1: Key type \"Opaque\" of table \"opaque\" can't be ordered"
+    );
+    let tags = r#"{"table":"tags","multimap":true,"key":{"name":"u64","class":"internal","width":8},"value":{"name":"Opaque","class":"user","width":null,"raw":true}}"#;
+    assert_eq!(
+        restore(&[tags]).unwrap_err(),
+        "Error restoring line 1: Value type \"Opaque\" of table \"tags\" can't be ordered"
+    );
+
+    drop(db);
+    std::fs::remove_file(&db_path).unwrap();
+    std::fs::remove_file(&dump_path).unwrap();
+}
@@ -0,0 +1,46 @@
+use serde_json::json;
+
+use crate::edit::edit;
+
+#[test]
+fn test_edit() {
+    let value = json!({"name": "apple", "price": 5});
+    let check = |v: &serde_json::Value| match v["price"].is_u64() {
+        true => Ok(()),
+        false => Err(eyre::eyre!("Invalid price")),
+    };
+
+    let pear = r#"sh -c 'echo "{\"name\": \"pear\", \"price\": 5}" > "$0"'"#;
+    let edited = edit(pear, &value, check).unwrap();
+    assert_eq!(edited, Some(json!({"name": "pear", "price": 5})));
+    assert_eq!(edit("true", &value, check).unwrap(), None);
+
+    assert!(edit("false", &value, check).is_err());
+    assert!(edit("'unbalanced", &value, check).is_err());
+
+    // The editor writes down the path of the file it's given
+    let seen = std::env::temp_dir().join(format!("redb-cli-edited-{}", std::process::id()));
+    let editor = format!("sh -c 'echo \"$1\" > \"$0\"' {}", seen.display());
+    let given = || std::fs::read_to_string(&seen).unwrap().trim().to_string();
+
+    // The file is private
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::cell::Cell::new(0);
+        let private = |_: &serde_json::Value| {
+            mode.set(std::fs::metadata(given())?.permissions().mode() & 0o777);
+            Ok(())
+        };
+        assert_eq!(edit(&editor, &value, private).unwrap(), None);
+        assert_eq!(mode.get(), 0o600);
+    }
+
+    // And removed even if the editor fails
+    let failing = format!("sh -c 'echo \"$1\" > \"$0\"; exit 1' {}", seen.display());
+    assert!(edit(&failing, &value, check).is_err());
+    let path = given();
+    std::fs::remove_file(&seen).unwrap();
+    assert!(!std::path::Path::new(&path).exists());
+}
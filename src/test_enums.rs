@@ -0,0 +1,223 @@
+use redb::{Key, TypeName, Value};
+use serde::Serialize;
+
+use crate::test;
+
+// redb-derive doesn't support enums, so these follow the encoding expected by the parser:
+// variant index byte, then the fields encoded like a tuple, padded if all variants are fixed width
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+enum Status {
+    Active,
+    Suspended { until: u64, reason: String },
+    Deleted(u64),
+    Moved(String, u32),
+}
+
+impl Value for Status {
+    type SelfType<'a> = Status;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Status
+    where
+        Self: 'a,
+    {
+        let (tag, data) = data.split_first().unwrap();
+        match tag {
+            0 => Status::Active,
+            1 => {
+                let (until, reason) = <(u64, &str)>::from_bytes(data);
+                Status::Suspended {
+                    until,
+                    reason: reason.into(),
+                }
+            }
+            2 => Status::Deleted(u64::from_bytes(data)),
+            3 => {
+                let (to, code) = <(&str, u32)>::from_bytes(data);
+                Status::Moved(to.into(), code)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Status) -> Vec<u8>
+    where
+        Self: 'b,
+    {
+        match value {
+            Status::Active => vec![0],
+            Status::Suspended { until, reason } => [
+                &[1],
+                <(u64, &str)>::as_bytes(&(*until, reason.as_str())).as_slice(),
+            ]
+            .concat(),
+            Status::Deleted(at) => [&[2][..], &u64::as_bytes(at)].concat(),
+            Status::Moved(to, code) => [
+                &[3],
+                <(&str, u32)>::as_bytes(&(to.as_str(), *code)).as_slice(),
+            ]
+            .concat(),
+        }
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(
+            "enum Status {Active, Suspended {until: u64, reason: String}, Deleted(u64), Moved(String, u32)}",
+        )
+    }
+}
+
+impl Key for Status {
+    fn compare(data1: &[u8], data2: &[u8]) -> std::cmp::Ordering {
+        Ord::cmp(&Self::from_bytes(data1), &Self::from_bytes(data2))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+enum Shape {
+    Empty,
+    Dot(i8),
+    Rect { w: u16, h: u32 },
+}
+
+impl Value for Shape {
+    type SelfType<'a> = Shape;
+    type AsBytes<'a> = [u8; 7];
+
+    fn fixed_width() -> Option<usize> {
+        Some(7)
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Shape
+    where
+        Self: 'a,
+    {
+        match data[0] {
+            0 => Shape::Empty,
+            1 => Shape::Dot(i8::from_bytes(&data[1..2])),
+            2 => {
+                let (w, h) = <(u16, u32)>::from_bytes(&data[1..]);
+                Shape::Rect { w, h }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Shape) -> [u8; 7]
+    where
+        Self: 'b,
+    {
+        let mut buf = [0; 7];
+        match value {
+            Shape::Empty => {}
+            Shape::Dot(x) => {
+                buf[0] = 1;
+                buf[1..2].copy_from_slice(&i8::as_bytes(x));
+            }
+            Shape::Rect { w, h } => {
+                buf[0] = 2;
+                buf[1..].copy_from_slice(&<(u16, u32)>::as_bytes(&(*w, *h)));
+            }
+        }
+        buf
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("enum Shape {Empty, Dot(i8), Rect {w: u16, h: u32}}")
+    }
+}
+
+impl Key for Shape {
+    fn compare(data1: &[u8], data2: &[u8]) -> std::cmp::Ordering {
+        Ord::cmp(&Self::from_bytes(data1), &Self::from_bytes(data2))
+    }
+}
+
+#[test]
+fn test_enums() {
+    test! {
+        // Unit, struct, newtype and tuple variants
+        let val: Status = Status::Active;
+        let val: Status = Status::Suspended { until: 1700000000, reason: "spam".into() };
+        let val: Status = Status::Deleted(42);
+        let val: Status = Status::Moved("eu-west".into(), 301);
+        let val: Status = Status::Moved("".into(), 0);
+
+        // Fixed width variants are padded
+        let val: Shape = Shape::Empty;
+        let val: Shape = Shape::Dot(-1);
+        let val: Shape = Shape::Rect { w: 3, h: 4 };
+
+        // Nested
+        let val: Option<Shape> = None;
+        let val: Option<Shape> = Some(Shape::Dot(7));
+        let val: Vec<Status> = vec![Status::Active, Status::Deleted(1)];
+        let val: (Shape, &str) = (Shape::Rect { w: 1, h: 2 }, "a");
+        let val: [Shape; 2] = [Shape::Empty, Shape::Dot(0)];
+    }
+
+    let tree = crate::parser::parse_tree(Status::type_name().name()).unwrap();
+    let mut buf = Vec::new();
+    for val in [
+        serde_json::json!("Deleted"),
+        serde_json::json!({"Active": null}),
+        serde_json::json!("Unknown"),
+        serde_json::json!({"Deleted": 1, "Active": null}),
+    ] {
+        assert!(crate::parser::encode(&tree, &val, &mut buf).is_err());
+    }
+    assert!(crate::parser::parse(&tree, &mut [9].as_ref()).is_err());
+}
+
+#[test]
+fn test_enum_ordering() {
+    macro_rules! test {
+        (let $v:ident: $t:ty = $l:expr, $r:expr;) => {
+            println!();
+            println!("{}", stringify!(let $v: $t = $l , $r;));
+
+            let l: $t = $l;
+            let r: $t = $r;
+            let l_buf = <$t as Value>::as_bytes(&l);
+            let r_buf = <$t as Value>::as_bytes(&r);
+            let (l_buf, r_buf): (&[u8], &[u8]) = (l_buf.as_ref(), r_buf.as_ref());
+
+            let tree = crate::parser::parse_tree(<$t as Value>::type_name().name()).unwrap();
+            assert!(crate::parser::can_order(&tree));
+            assert_eq!(tree.compare(l_buf, r_buf), <$t as Key>::compare(l_buf, r_buf));
+
+            let min = crate::parser::min_value(&tree).unwrap();
+            let mut min_buf = Vec::new();
+            crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+            assert!(tree.compare(&min_buf, l_buf).is_le());
+            assert!(tree.compare(&min_buf, r_buf).is_le());
+        };
+        ($(let $v:ident: $t:ty = $l:expr, $r:expr;)*) => {
+            $( test!( let $v: $t = $l, $r; ); )*
+        };
+    }
+
+    test! {
+        let val: Status = Status::Active, Status::Deleted(0);
+        let val: Status = Status::Moved("a".into(), 1), Status::Deleted(9);
+        let val: Status = Status::Moved("a".into(), 2), Status::Moved("a".into(), 1);
+        let val: Status = Status::Moved("ab".into(), 1), Status::Moved("b".into(), 0);
+        let val: Status = Status::Suspended { until: 1, reason: "z".into() },
+            Status::Suspended { until: 2, reason: "a".into() };
+        let val: Shape = Shape::Empty, Shape::Empty;
+        let val: Shape = Shape::Dot(-5), Shape::Dot(5);
+        let val: Shape = Shape::Rect { w: 1, h: 9 }, Shape::Dot(100);
+        let val: (Shape, u8) = (Shape::Dot(1), 0), (Shape::Dot(1), 1);
+    }
+
+    // Unknown variants come after the known ones, by their bytes
+    let tree = crate::parser::parse_tree("enum Shape {Empty, Dot(i32)}").unwrap();
+    assert!(tree.compare(&[1, 0, 0, 0, 1], &[9, 0, 0, 0, 0]).is_lt());
+    assert!(tree.compare(&[9, 0, 0, 0, 1], &[9, 0, 0, 0, 0]).is_gt());
+    assert!(tree.compare(&[9, 0, 0, 0, 0], &[9, 0, 0, 0, 0]).is_eq());
+}
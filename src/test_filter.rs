@@ -0,0 +1,150 @@
+use serde_json::{Value, json};
+
+use crate::{
+    filter::{Fields, Filter},
+    parser::parse_tree,
+};
+
+fn eval(expr: &str, input: &Value) -> Option<Value> {
+    Filter::new(expr).unwrap().eval(input).unwrap()
+}
+
+#[test]
+fn test_filter() {
+    let entry = json!({
+        "key": 2,
+        "value": {"name": "melon", "price": 15, "active": true, "tags": ["fruit", "big"]},
+    });
+    let matches = |expr: &str| {
+        let (mut key, mut value) = (entry["key"].clone(), entry["value"].clone());
+        let res = Filter::new(expr)
+            .unwrap()
+            .matches(&mut key, &mut value)
+            .unwrap();
+        assert_eq!(json!({"key": key, "value": value}), entry, "{expr}");
+        res
+    };
+
+    assert!(matches(".value.active and .value.price > 10"));
+    assert!(!matches(".value.active and .value.price > 20"));
+    assert!(matches("select(.value.tags | contains([\"big\"]))"));
+    assert!(matches(".value.missing // true"));
+    assert!(!matches(".value.missing"));
+    assert!(!matches("empty"));
+    assert!(matches(
+        ".value.tags[-1] == \"big\" and .value[\"name\"] == .value.name"
+    ));
+    assert!(matches("(.value.name | startswith(\"me\")) or false"));
+    assert!(matches(".value.active | not | not"));
+
+    assert_eq!(eval(".value.tags | length", &entry), Some(json!(2)));
+    assert_eq!(
+        eval(".value | keys", &entry),
+        Some(json!(["active", "name", "price", "tags"]))
+    );
+    assert_eq!(
+        eval(".value.tags | map(length)", &entry),
+        Some(json!([5, 3]))
+    );
+    assert_eq!(
+        eval("[.key, .value.name]", &entry),
+        Some(json!([2, "melon"]))
+    );
+    assert_eq!(eval(".value.price / 2", &entry), Some(json!(7.5)));
+    assert_eq!(eval("-.key * 3 + 1", &entry), Some(json!(-5)));
+    assert_eq!(eval("7 % 3 - 1 - 1", &entry), Some(json!(-1)));
+    assert_eq!(
+        eval("\"a\" + \"b\" | ascii_upcase", &entry),
+        Some(json!("AB"))
+    );
+    assert_eq!(eval(".key | tostring", &entry), Some(json!("2")));
+    assert_eq!(eval("\"1.5\" | tonumber", &entry), Some(json!(1.5)));
+    assert_eq!(eval("[1, 2, 3] - [2]", &entry), Some(json!([1, 3])));
+    assert_eq!(eval(".value | type", &entry), Some(json!("object")));
+
+    // Order and equality of jq, numbers are compared by value
+    assert_eq!(eval("1 == 1.0", &entry), Some(json!(true)));
+    assert_eq!(
+        eval("null < false and false < 0 and 0 < \"\"", &entry),
+        Some(json!(true))
+    );
+    assert_eq!(
+        eval("\"\" < [] and [] < .value and [1] < [1, 0]", &entry),
+        Some(json!(true))
+    );
+    assert_eq!(
+        eval(
+            "170141183460469231731687303715884105727 > 170141183460469231731687303715884105726",
+            &entry
+        ),
+        Some(json!(true))
+    );
+
+    let err = |expr: &str| match Filter::new(expr) {
+        Ok(filter) => filter.eval(&entry).unwrap_err().to_string(),
+        Err(err) => err.to_string(),
+    };
+    assert_eq!(err(".value.name.x"), "Cannot index string with \"x\"");
+    assert_eq!(err(".value[0]"), "Cannot index object with number");
+    assert_eq!(
+        err(".key + \"a\""),
+        "number (2) and string (\"a\") cannot be added"
+    );
+    assert_eq!(
+        err(".key / 0"),
+        "2 and 0 cannot be divided because the divisor is zero"
+    );
+    assert_eq!(
+        err(".key % 0.5"),
+        "2 and 0.5 cannot be divided because the divisor is zero"
+    );
+    let min = "(-170141183460469231731687303715884105727 - 1)";
+    for op in ["/", "%"] {
+        assert_eq!(
+            err(&format!("{min} {op} -1")),
+            format!(
+                "{} and -1 cannot be divided because the result overflows",
+                i128::MIN
+            )
+        );
+    }
+    assert_eq!(err("true | length"), "boolean (true) has no length");
+    assert_eq!(err("select(1; 2)"), "Unknown function select/2");
+    assert_eq!(err("frobnicate"), "Unknown function frobnicate/0");
+    assert!(err(".value.price >").contains("expected"));
+}
+
+#[test]
+fn test_fields() {
+    let ty =
+        parse_tree("Item {name: String, price: u64, address: Address {city: String, zip: u32}}")
+            .unwrap();
+    let val = json!({"name": "a", "price": 1, "address": {"city": "b", "zip": 2}});
+
+    let fields = Fields::new(&["price".into(), "address.city".into()]);
+    fields.check(&ty).unwrap();
+    assert_eq!(
+        fields.project(val.clone()),
+        json!({"price": 1, "address": {"city": "b"}})
+    );
+    assert!(fields.keeps(&["address".into()]));
+    assert!(fields.keeps(&["address".into(), "city".into()]));
+    assert!(!fields.keeps(&["address".into(), "zip".into()]));
+
+    // Entries that failed to decode are kept
+    let raw = json!({"$raw": "00"});
+    assert_eq!(fields.project(raw.clone()), raw);
+    assert_eq!(Fields::default().project(val.clone()), val);
+
+    let err = |field: &str| {
+        Fields::new(&[field.into()])
+            .check(&ty)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(err("nam"), "No field \"nam\" in Item");
+    assert_eq!(
+        err("name.len"),
+        "Can't select \"name.len\" of String, only struct fields can be selected"
+    );
+}
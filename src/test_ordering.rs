@@ -13,7 +13,7 @@ fn test_ordering() {
             let r_buf = <$t as redb::Value>::as_bytes(&r);
 
             let tree = crate::parser::parse_tree(ty.name()).unwrap();
-            assert_eq!(crate::parser::parse_size(tree.clone()).unwrap(), width);
+            assert_eq!(crate::parser::parse_size(&tree), width);
 
             let l_buf: &[u8] = l_buf.as_ref();
             let r_buf: &[u8] = r_buf.as_ref();
@@ -21,10 +21,10 @@ fn test_ordering() {
             let l_serde = serde_json::to_string_pretty(&l).unwrap();
             let r_serde = serde_json::to_string_pretty(&r).unwrap();
 
-            let l_parsed_value = crate::parser::parse(tree.clone(), &mut l_buf.as_ref()).unwrap();
+            let l_parsed_value = crate::parser::parse(&tree, &mut l_buf.as_ref()).unwrap();
             let l_parsed = serde_json::to_string_pretty(&l_parsed_value).unwrap();
 
-            let r_parsed_value = crate::parser::parse(tree.clone(), &mut r_buf.as_ref()).unwrap();
+            let r_parsed_value = crate::parser::parse(&tree, &mut r_buf.as_ref()).unwrap();
             let r_parsed = serde_json::to_string_pretty(&r_parsed_value).unwrap();
 
             println!("{l_serde}\n{l_parsed}");
@@ -34,17 +34,19 @@ fn test_ordering() {
             assert_eq!(r_serde, r_parsed);
 
             let mut l_encoded = Vec::new();
-            crate::parser::encode(tree.clone(), &l_parsed_value, &mut l_encoded).unwrap();
+            crate::parser::encode(&tree, &l_parsed_value, &mut l_encoded).unwrap();
             crate::utils::dump_assert_eq(l_buf, &l_encoded);
 
             let mut r_encoded = Vec::new();
-            crate::parser::encode(tree.clone(), &r_parsed_value, &mut r_encoded).unwrap();
+            crate::parser::encode(&tree, &r_parsed_value, &mut r_encoded).unwrap();
             crate::utils::dump_assert_eq(r_buf, &r_encoded);
 
             assert_eq!(
-                crate::parser::ordering(&l_parsed_value, &r_parsed_value).unwrap(),
+                crate::parser::ordering(&tree, &l_parsed_value, &r_parsed_value).unwrap(),
                 <$t as redb::Key>::compare(l_buf, r_buf),
             );
+
+            assert_eq!(tree.compare(l_buf, r_buf), <$t as redb::Key>::compare(l_buf, r_buf));
         };
         ($(let $v:ident: $t:ty = $l:expr, $r:expr;)*) => {
             $( test!( let $v: $t = $l, $r; ); )*
@@ -239,3 +241,273 @@ fn test_ordering() {
         let val: (Option<i32>, (i32, &str)) = (Some(2), (2, "a")), (Some(1), (2, "b"));
     }
 }
+
+#[test]
+fn test_min_value() {
+    macro_rules! test {
+        (let $v:ident: $t:ty = $val:expr;) => {
+            println!();
+            println!("{}", stringify!(let $v: $t = $val;));
+
+            let $v: $t = $val;
+            let ty = <$t as redb::Value>::type_name();
+            let tree = crate::parser::parse_tree(ty.name()).unwrap();
+
+            let min = crate::parser::min_value(&tree).unwrap();
+            let mut min_buf = Vec::new();
+            crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+
+            let buf = <$t as redb::Value>::as_bytes(&$v);
+            assert!(<$t as redb::Key>::compare(&min_buf, buf.as_ref()).is_le());
+        };
+        ($(let $v:ident: $t:ty = $val:expr;)*) => {
+            $( test!( let $v: $t = $val; ); )*
+        };
+    }
+
+    test! {
+        let val: i8 = i8::MIN;
+        let val: i64 = -1;
+        let val: u64 = 0;
+        let val: bool = false;
+        let val: char = '\0';
+        let val: &str = "";
+        let val: Option<u32> = None;
+        let val: [i32; 2] = [i32::MIN, 0];
+        let val: (u64, i32) = (0, i32::MIN);
+        let val: (u64, (i8, &str)) = (1, (-1, "a"));
+    }
+
+    let tree = crate::parser::parse_tree("(u64,i32,&str)").unwrap();
+    let padded = crate::parser::pad_prefix(&tree, serde_json::json!([5])).unwrap();
+    assert_eq!(padded, serde_json::json!([5, i32::MIN, ""]));
+}
+
+#[test]
+fn test_comparator() {
+    macro_rules! test {
+        (let $v:ident: $t:ty = $l:expr, $r:expr;) => {
+            println!();
+            println!("{}", stringify!(let $v: $t = $l , $r;));
+
+            let l: $t = $l;
+            let r: $t = $r;
+            let ty = <$t as redb::Value>::type_name();
+            let l_buf = <$t as redb::Value>::as_bytes(&l);
+            let r_buf = <$t as redb::Value>::as_bytes(&r);
+
+            let tree = crate::parser::parse_tree(ty.name()).unwrap();
+            assert_eq!(tree.compare(l_buf.as_ref(), r_buf.as_ref()), Ord::cmp(&l, &r));
+        };
+        ($(let $v:ident: $t:ty = $l:expr, $r:expr;)*) => {
+            $( test!( let $v: $t = $l, $r; ); )*
+        };
+    }
+
+    // Variable width array elements are addressed by an offset table
+    test! {
+        let val: [&str; 2] = ["a", "b"], ["a", "c"];
+        let val: [&str; 2] = ["ab", "a"], ["a", "b"];
+        let val: [&str; 2] = ["", "z"], ["a", ""];
+        let val: [Option<&str>; 2] = [None, Some("a")], [Some(""), None];
+    }
+
+    // Vectors compare element-wise, then by length
+    test! {
+        let val: Vec<u16> = vec![1, 2], vec![1, 2, 0];
+        let val: Vec<u16> = vec![256], vec![1, 2];
+        let val: Vec<i32> = vec![-1], vec![];
+        let val: Vec<&str> = vec!["b"], vec!["a", "c"];
+        let val: Vec<&str> = vec!["a".repeat(300).leak()], vec!["a".repeat(300).leak(), ""];
+        let val: Vec<(u8, &str)> = vec![(1, "a"), (1, "b")], vec![(1, "a"), (0, "c")];
+    }
+
+    // Tuples with several variable width fields
+    test! {
+        let val: (&str, &str, u8) = ("a", "bc", 1), ("ab", "c", 0);
+        let val: (&str, &str, u8) = ("a", "bc", 1), ("a", "bc", 2);
+        let val: (&[u8], i64, &str) = (&[1, 2], -5, "x"), (&[1, 2], 5, "a");
+        let val: (u8, ((), &str)) = (0, ((), "b")), (0, ((), "a"));
+    }
+}
+
+#[test]
+fn test_float_ordering() {
+    macro_rules! test {
+        (let $v:ident: $t:ty = $l:expr, $r:expr; $cmp:expr) => {
+            println!();
+            println!("{}", stringify!(let $v: $t = $l , $r;));
+
+            let l: $t = $l;
+            let r: $t = $r;
+            let ty = <$t as redb::Value>::type_name();
+            let l_buf = <$t as redb::Value>::as_bytes(&l);
+            let r_buf = <$t as redb::Value>::as_bytes(&r);
+            let (l_buf, r_buf): (&[u8], &[u8]) = (l_buf.as_ref(), r_buf.as_ref());
+
+            let tree = crate::parser::parse_tree(ty.name()).unwrap();
+            assert!(crate::parser::can_order(&tree));
+            assert_eq!(tree.compare(l_buf, r_buf), $cmp(&l, &r));
+
+            // Non-finite values survive the round trip through JSON
+            for (val, buf) in [(&l, l_buf), (&r, r_buf)] {
+                let parsed = crate::parser::parse(&tree, &mut buf.as_ref()).unwrap();
+                println!("{val:?} -> {parsed}");
+                let mut encoded = Vec::new();
+                crate::parser::encode(&tree, &parsed, &mut encoded).unwrap();
+                crate::utils::dump_assert_eq(buf, &encoded);
+            }
+        };
+        ($(let $v:ident: $t:ty = $l:expr, $r:expr;)* with $cmp:expr) => {
+            $( test!( let $v: $t = $l, $r; $cmp); )*
+        };
+    }
+
+    test! {
+        let val: f64 = 1.5, 2.5;
+        let val: f64 = -1.0, 1.0;
+        let val: f64 = -0.0, 0.0;
+        let val: f64 = f64::MIN, f64::MAX;
+        let val: f64 = f64::NEG_INFINITY, f64::MIN;
+        let val: f64 = f64::INFINITY, f64::MAX;
+        let val: f64 = f64::NAN, f64::INFINITY;
+        let val: f64 = -f64::NAN, f64::NEG_INFINITY;
+        let val: f64 = f64::MIN_POSITIVE, f64::EPSILON;
+        with f64::total_cmp
+    }
+
+    test! {
+        let val: f32 = 1.5, 2.5;
+        let val: f32 = -0.0, 0.0;
+        let val: f32 = f32::MIN, -1e-30;
+        let val: f32 = f32::NAN, f32::INFINITY;
+        let val: f32 = -f32::NAN, f32::NEG_INFINITY;
+        with f32::total_cmp
+    }
+
+    test! {
+        let val: (f64, f64) = (51.5, -0.12), (51.5, 0.0);
+        let val: (f64, f64) = (-33.9, 151.2), (40.7, -74.0);
+        let val: (f64, &str) = (0.5, "b"), (0.5, "a");
+        let val: Option<f32> = None, Some(f32::NEG_INFINITY);
+        with |l: &_, r: &_| PartialOrd::partial_cmp(l, r).unwrap()
+    }
+
+    let tree = crate::parser::parse_tree("f64").unwrap();
+    let l = crate::parser::parse(&tree, &mut 1.5f64.to_le_bytes().as_ref()).unwrap();
+    let r = crate::parser::parse(&tree, &mut 2f64.to_le_bytes().as_ref()).unwrap();
+    assert!(crate::parser::ordering(&tree, &l, &r).unwrap().is_lt());
+
+    for bits in ["f32", "f64"] {
+        let tree = crate::parser::parse_tree(bits).unwrap();
+        let min = crate::parser::min_value(&tree).unwrap();
+        let mut min_buf = Vec::new();
+        crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+        for val in ["-NaN", "-inf", "NaN"] {
+            let mut buf = Vec::new();
+            crate::parser::encode(&tree, &serde_json::json!(val), &mut buf).unwrap();
+            assert!(tree.compare(&min_buf, &buf).is_lt());
+        }
+    }
+
+    // NaNs with any payload sort after the minimum
+    let tree = crate::parser::parse_tree("f64").unwrap();
+    let mut min_buf = Vec::new();
+    let min = crate::parser::min_value(&tree).unwrap();
+    crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+    assert_eq!(min_buf, f64::from_bits(u64::MAX).to_le_bytes());
+    let nan = f64::from_bits(u64::MAX - 1);
+    assert!(tree.compare(&min_buf, &nan.to_le_bytes()).is_lt());
+    let tree = crate::parser::parse_tree("f32").unwrap();
+    let mut min_buf = Vec::new();
+    let min = crate::parser::min_value(&tree).unwrap();
+    crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+    assert_eq!(min_buf, f32::from_bits(u32::MAX).to_le_bytes());
+}
+
+#[test]
+fn test_struct_ordering() {
+    use redb::Value as _;
+    use redb_derive::{Key, Value};
+
+    // Field order differs from the alphabetical order of Json object keys
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Key, Value)]
+    struct Event {
+        time: u64,
+        kind: String,
+        attempt: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Key, Value)]
+    struct Id {
+        shard: i16,
+    }
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Key, Value)]
+    struct Nested {
+        id: Id,
+        path: Vec<String>,
+        event: Event,
+    }
+
+    macro_rules! test {
+        (let $v:ident: $t:ty = $l:expr, $r:expr;) => {
+            println!();
+            println!("{}", stringify!(let $v: $t = $l , $r;));
+
+            let l: $t = $l;
+            let r: $t = $r;
+            let ty = <$t as redb::Value>::type_name();
+            let l_buf = <$t as redb::Value>::as_bytes(&l);
+            let r_buf = <$t as redb::Value>::as_bytes(&r);
+            let (l_buf, r_buf): (&[u8], &[u8]) = (l_buf.as_ref(), r_buf.as_ref());
+
+            let tree = crate::parser::parse_tree(ty.name()).unwrap();
+            assert!(crate::parser::can_order(&tree));
+            assert_eq!(tree.compare(l_buf, r_buf), <$t as redb::Key>::compare(l_buf, r_buf));
+
+            let min = crate::parser::min_value(&tree).unwrap();
+            let mut min_buf = Vec::new();
+            crate::parser::encode(&tree, &min, &mut min_buf).unwrap();
+            assert!(tree.compare(&min_buf, l_buf).is_le());
+            assert!(tree.compare(&min_buf, r_buf).is_le());
+        };
+        ($(let $v:ident: $t:ty = $l:expr, $r:expr;)*) => {
+            $( test!( let $v: $t = $l, $r; ); )*
+        };
+    }
+
+    test! {
+        let val: Event = Event { time: 1, kind: "b".into(), attempt: None },
+            Event { time: 2, kind: "a".into(), attempt: None };
+        let val: Event = Event { time: 1, kind: "b".into(), attempt: Some(0) },
+            Event { time: 1, kind: "a".into(), attempt: Some(9) };
+        let val: Event = Event { time: 1, kind: "a".into(), attempt: Some(1) },
+            Event { time: 1, kind: "a".into(), attempt: None };
+        let val: Id = Id { shard: -1 }, Id { shard: 1 };
+        let val: Nested = Nested {
+                id: Id { shard: 0 },
+                path: vec!["z".into()],
+                event: Event { time: 0, kind: "".into(), attempt: None },
+            },
+            Nested {
+                id: Id { shard: 0 },
+                path: vec!["a".into(), "b".into()],
+                event: Event { time: 9, kind: "".into(), attempt: None },
+            };
+        let val: (Id, &str) = (Id { shard: 3 }, "a"), (Id { shard: -3 }, "b");
+    }
+
+    // Missing fields are padded with the smallest values
+    let tree = crate::parser::parse_tree(<Event as redb::Value>::type_name().name()).unwrap();
+    let padded = crate::parser::pad_prefix(&tree, serde_json::json!({"time": 5})).unwrap();
+    assert_eq!(
+        padded,
+        serde_json::json!({"time": 5, "kind": "", "attempt": null})
+    );
+    let err = crate::parser::pad_prefix(&tree, serde_json::json!({"time": 5, "attempt": 1}));
+    assert_eq!(
+        err.unwrap_err().to_string(),
+        "Missing field \"kind\" before \"attempt\""
+    );
+}
@@ -0,0 +1,65 @@
+use redb::{Database, TableDefinition, backends::InMemoryBackend};
+use serde_json::{Value, json};
+
+use crate::{
+    DB, Data,
+    cli::CliArgs,
+    print::{Format, Printer},
+    process,
+    utils::Buf,
+};
+
+const NUMBERS: TableDefinition<u64, &str> = TableDefinition::new("numbers");
+
+fn output(db: &DB, format: Format) -> String {
+    let args = CliArgs {
+        table: Some("numbers".into()),
+        format,
+        ..Default::default()
+    };
+    let buf = Buf::default();
+    let mut data = Data {
+        printer: Printer::with_writer(&args, Box::new(buf.clone())).unwrap(),
+        ..Default::default()
+    };
+    process::process(&args, db, &mut data).unwrap();
+    data.printer.finish().unwrap();
+    buf.take()
+}
+
+#[test]
+fn test_storage_order() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let w = db.begin_write().unwrap();
+    let mut numbers = w.open_table(NUMBERS).unwrap();
+    numbers.insert(100, "hundred").unwrap();
+    numbers.insert(9, "nine").unwrap();
+    numbers.insert(10, "ten").unwrap();
+    drop(numbers);
+    w.commit().unwrap();
+    let db = DB::RW(db);
+
+    // Numeric order of the keys, not the order of their strings
+    assert_eq!(
+        output(&db, Format::Text),
+        "9: nine\n10: ten\n100: hundred\n"
+    );
+    assert_eq!(
+        output(&db, Format::Json),
+        "{\n  \"9\": \"nine\",\n  \"10\": \"ten\",\n  \"100\": \"hundred\"\n}\n"
+    );
+
+    // Pairs keep keys typed, as JSON numbers
+    let pairs: Value = serde_json::from_str(&output(&db, Format::Pairs)).unwrap();
+    assert_eq!(
+        pairs,
+        json!([
+            {"key": 9, "value": "nine"},
+            {"key": 10, "value": "ten"},
+            {"key": 100, "value": "hundred"},
+        ])
+    );
+    assert!(pairs[0]["key"].is_u64());
+}
@@ -15,13 +15,13 @@ macro_rules! test {
         let serde = serde_json::to_value(&$v).unwrap();
         let serde = serde_json::to_string_pretty(&serde).unwrap();
 
-        let parsed_value = crate::parser::parse(tree.clone(), &mut buf.as_ref()).unwrap();
+        let parsed_value = crate::parser::parse(&tree, &mut buf.as_ref()).unwrap();
         let parsed = serde_json::to_string_pretty(&parsed_value).unwrap();
 
         println!("{serde}\n{parsed}");
         assert_eq!(serde, parsed);
 
-        assert_eq!(crate::parser::parse_size(tree.clone()).unwrap(), width);
+        assert_eq!(crate::parser::parse_size(&tree), width);
 
         let mut encoded = Vec::new();
         crate::parser::encode_type(ty.name(), &parsed_value, &mut encoded).unwrap();
@@ -101,6 +101,15 @@ fn test_parser() {
         let val: Vec<bool> = vec![true, false, true];
     }
 
+    // Arrays
+    test! {
+        let val: [u16; 3] = [1, 2, 3];
+        let val: [&str; 2] = ["a", "bc"];
+        let val: [Vec<u8>; 3] = [vec![], vec![1], vec![2, 3]];
+        let val: [Option<&str>; 2] = [None, Some("x")];
+        let val: ([&str; 2], u8) = (["", "y"], 7);
+    }
+
     // &str slices
     test! {
         let val: &str = "";
@@ -198,3 +207,220 @@ fn test_parser() {
         let val: Vec<&str> = vec!["a", "bc", "def"];
     }
 }
+
+#[test]
+fn test_type_desc() {
+    use crate::parser::{TypeDesc, parse_tree};
+
+    let int = |bits, signed| TypeDesc::Int { bits, signed };
+
+    assert_eq!(
+        parse_tree("(u64,Option<i8>)").unwrap(),
+        TypeDesc::Tuple {
+            fields: vec![
+                int(64, false),
+                TypeDesc::Option {
+                    ty: Box::new(int(8, true)),
+                    width: Some(2),
+                },
+            ],
+            width: Some(10),
+        },
+    );
+    assert_eq!(
+        parse_tree("Log { time: u64, tags: Vec<&str> }").unwrap(),
+        TypeDesc::Struct {
+            name: "Log".into(),
+            fields: vec![
+                ("time".into(), int(64, false)),
+                ("tags".into(), TypeDesc::Vec(Box::new(TypeDesc::String))),
+            ],
+            width: None,
+        },
+    );
+    assert_eq!(
+        parse_tree("enum E { A, B(u8), C(u8, u16), D { x: i32 } }").unwrap(),
+        TypeDesc::Enum {
+            name: "E".into(),
+            variants: vec![
+                ("A".into(), None),
+                ("B".into(), Some(int(8, false))),
+                (
+                    "C".into(),
+                    Some(TypeDesc::Tuple {
+                        fields: vec![int(8, false), int(16, false)],
+                        width: Some(3),
+                    }),
+                ),
+                (
+                    "D".into(),
+                    Some(TypeDesc::Struct {
+                        name: "D".into(),
+                        fields: vec![("x".into(), int(32, true))],
+                        width: Some(4),
+                    }),
+                ),
+            ],
+            width: Some(5),
+        },
+    );
+    assert_eq!(parse_tree("enum E {}").unwrap().width(), Some(1));
+    assert_eq!(parse_tree("enum E { A(String) }").unwrap().width(), None);
+    assert_eq!(
+        parse_tree("my_crate::model::Page<'a, u64> { n: u8 }").unwrap(),
+        TypeDesc::Struct {
+            name: "my_crate::model::Page<'a, u64>".into(),
+            fields: vec![("n".into(), int(8, false))],
+            width: Some(1),
+        },
+    );
+    assert_eq!(
+        parse_tree("Option<Wrapper<i8>(i8)>").unwrap(),
+        TypeDesc::Option {
+            ty: Box::new(TypeDesc::TupleStruct {
+                name: "Wrapper<i8>".into(),
+                fields: vec![int(8, true)],
+                width: Some(1),
+            }),
+            width: Some(2),
+        },
+    );
+    assert_eq!(
+        parse_tree("(u8Marker, Stringy)").unwrap(),
+        TypeDesc::Tuple {
+            fields: vec![
+                TypeDesc::TupleStruct {
+                    name: "u8Marker".into(),
+                    fields: vec![],
+                    width: Some(0),
+                },
+                TypeDesc::TupleStruct {
+                    name: "Stringy".into(),
+                    fields: vec![],
+                    width: Some(0),
+                },
+            ],
+            width: Some(0),
+        },
+    );
+    assert_eq!(parse_tree("[[u8;2];3]").unwrap().width(), Some(6));
+    assert!(parse_tree("[u8; x]").is_err());
+}
+
+#[test]
+fn test_raw() {
+    use crate::parser::{encode, parse_from_tree, parse_tree, raw_value};
+    use serde_json::json;
+
+    // Unknown types are shown as hex
+    let tree = parse_tree("u32").unwrap().into_raw();
+    assert_eq!(tree.width(), Some(4));
+    assert_eq!(
+        parse_from_tree(&tree, &[1, 0, 0xab, 0]).unwrap(),
+        json!("0100ab00")
+    );
+    let mut buf = Vec::new();
+    encode(&tree, &json!("0x0100ab00"), &mut buf).unwrap();
+    assert_eq!(buf, [1, 0, 0xab, 0]);
+    assert!(encode(&tree, &json!("0100"), &mut Vec::new()).is_err());
+    assert!(encode(&tree, &json!("01x0"), &mut Vec::new()).is_err());
+
+    // Entries that can't be decoded are written back as they were
+    let tree = parse_tree("(u8, String)").unwrap();
+    let data = [7, 0xff, 0xfe];
+    assert!(parse_from_tree(&tree, &data).is_err());
+    let val = raw_value(&data);
+    assert_eq!(val, json!({"$raw": "07fffe"}));
+    let mut buf = Vec::new();
+    encode(&tree, &val, &mut buf).unwrap();
+    assert_eq!(buf, data);
+
+    let tree = parse_tree("u16").unwrap();
+    assert!(encode(&tree, &json!({"$raw": "07"}), &mut Vec::new()).is_err());
+    assert!(parse_from_tree(&tree, &[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_codec_errors() {
+    use crate::parser::{CodecError, encode, parse_from_tree, parse_tree};
+    use serde_json::json;
+
+    let encode_err = |ty: &str, val| {
+        let err = encode(&parse_tree(ty).unwrap(), &val, &mut Vec::new()).unwrap_err();
+        err.to_string()
+    };
+    let parse_err =
+        |ty: &str, data: &[u8]| parse_from_tree(&parse_tree(ty).unwrap(), data).unwrap_err();
+
+    assert_eq!(encode_err("u8", json!(300)), "expected u8, found 300");
+    assert_eq!(encode_err("i16", json!(-1.5)), "expected i16, found -1.5");
+    assert_eq!(
+        encode_err("bool", json!("yes")),
+        "expected bool, found \"yes\""
+    );
+    assert_eq!(
+        encode_err("char", json!("ab")),
+        "expected char, found \"ab\""
+    );
+    assert_eq!(
+        encode_err("f32", json!(1e300)),
+        "expected f32, found 1e+300"
+    );
+    assert_eq!(
+        encode_err(
+            "Log { time: u64, tags: Vec<&str> }",
+            json!({"time": 1, "tags": ["a", "b", "c", 4]})
+        ),
+        "Log.tags[3]: expected String, found 4"
+    );
+    assert_eq!(
+        encode_err("Log { time: u64 }", json!({"time": 1, "level": 2})),
+        "Log: expected a field of Log, found unknown field \"level\""
+    );
+    assert_eq!(
+        encode_err("Log { time: u64 }", json!({})),
+        "Log.time: expected u64, found missing field"
+    );
+    assert_eq!(
+        encode_err("(u8, [Option<i8>; 2])", json!([1, [null, 128]])),
+        ".1[1]: expected i8, found 128"
+    );
+    assert_eq!(
+        encode_err("enum Status { Active, Banned(u8) }", json!({"Banned": "x"})),
+        "Status::Banned: expected u8, found \"x\""
+    );
+    assert_eq!(
+        encode_err("enum Status { Active }", json!("Gone")),
+        "Status: expected a variant of Status, found \"Gone\""
+    );
+
+    assert_eq!(
+        parse_err("(u8, String)", &[7, b'a', 0xff]),
+        CodecError {
+            path: ".1".into(),
+            offset: Some(2),
+            expected: "UTF-8 string".into(),
+            actual: "byte 0xff".into(),
+        }
+    );
+    assert_eq!(
+        parse_err("Pair { a: bool, b: u32 }", &[2, 0, 0, 0, 0]).to_string(),
+        "Pair.a: expected bool, found 0x02 at byte 0"
+    );
+    assert_eq!(
+        parse_err("Vec<u16>", &[2, 1, 0, 1]).to_string(),
+        "[1]: expected 2 bytes, found 1 bytes at byte 3"
+    );
+    assert_eq!(
+        parse_err("(u8, Option<char>)", &[0, 1, 0, 0xd8, 0]).to_string(),
+        ".1: expected char, found 0xd800 at byte 2"
+    );
+    assert_eq!(
+        parse_err("enum Status { Active }", &[3]).to_string(),
+        "Status: expected a variant of Status, found 3 at byte 0"
+    );
+    assert_eq!(
+        parse_err("u16", &[1, 2, 3]).to_string(),
+        "expected end of data, found 1 trailing bytes at byte 2"
+    );
+}
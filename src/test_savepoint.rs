@@ -0,0 +1,44 @@
+use redb::{Database, ReadableTable, TableDefinition, backends::InMemoryBackend};
+
+use crate::DB;
+
+const USERS: TableDefinition<u64, &str> = TableDefinition::new("users");
+
+#[test]
+fn test_savepoints() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let db = DB::RW(db);
+    let put = |k: u64, v: &str| {
+        let w = db.begin_write().unwrap();
+        w.open_table(USERS).unwrap().insert(k, v).unwrap();
+        w.commit().unwrap();
+    };
+    let keys = || {
+        let r = db.begin_read().unwrap();
+        let users = r.open_table(USERS).unwrap();
+        users
+            .iter()
+            .unwrap()
+            .map(|e| e.unwrap().0.value())
+            .collect::<Vec<_>>()
+    };
+
+    put(1, "alice");
+    let first = db.persistent_savepoint().unwrap();
+    put(2, "bob");
+    let second = db.persistent_savepoint().unwrap();
+    put(3, "carol");
+    assert_eq!(db.list_persistent_savepoints().unwrap(), [first, second]);
+
+    // Savepoints created after the restored one are gone
+    db.restore_savepoint(first).unwrap();
+    assert_eq!(keys(), [1]);
+    assert_eq!(db.list_persistent_savepoints().unwrap(), [first]);
+    assert!(db.restore_savepoint(second).is_err());
+
+    db.delete_persistent_savepoint(first).unwrap();
+    assert!(db.delete_persistent_savepoint(first).is_err());
+    assert!(db.list_persistent_savepoints().unwrap().is_empty());
+}
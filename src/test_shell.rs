@@ -0,0 +1,83 @@
+use redb::{Database, MultimapTableDefinition, TableDefinition, backends::InMemoryBackend};
+use rustyline::{Context, completion::Completer, history::DefaultHistory};
+
+use crate::{DB, cli::CliArgs, shell::Session, utils::Buf};
+
+#[test]
+fn test_shell() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let w = db.begin_write().unwrap();
+    w.open_table(TableDefinition::<&str, u64>::new("users"))
+        .unwrap()
+        .insert("alice", 1)
+        .unwrap();
+    w.open_multimap_table(MultimapTableDefinition::<u64, &str>::new("tags"))
+        .unwrap();
+    let mut counts = w
+        .open_table(TableDefinition::<u64, u64>::new("counts"))
+        .unwrap();
+    for i in 0..=10_000 {
+        counts.insert(i, i).unwrap();
+    }
+    drop(counts);
+    w.commit().unwrap();
+
+    let buf = Buf::default();
+    let out = buf.clone();
+    let mut shell = Session::with_writer(CliArgs::default(), DB::RW(db), move || {
+        Box::new(out.clone())
+    });
+    assert!(shell.exec("get alice").is_err());
+    assert!(shell.exec("use missing").is_err());
+
+    shell.exec("use users").unwrap();
+    shell.exec("put 'bob smith' 2").unwrap();
+    assert!(shell.exec("put carol x").is_err());
+
+    shell.exec("begin").unwrap();
+    assert!(shell.exec("begin").is_err());
+    shell.exec("put carol 3").unwrap();
+    shell.exec("rm alice").unwrap();
+    shell.exec("get carol").unwrap();
+    assert_eq!(buf.take(), "3\n");
+    assert!(shell.exec("get alice").is_err());
+    shell.exec("rollback").unwrap();
+    assert!(shell.exec("get carol").is_err());
+    shell.exec("get alice").unwrap();
+    assert_eq!(buf.take(), "1\n");
+    shell.exec("range --from b --filter '.value > 1'").unwrap();
+    assert_eq!(buf.take(), "bob smith: 2\n");
+    assert!(shell.exec("commit").is_err());
+
+    let history = DefaultHistory::new();
+    let ctx = Context::new(&history);
+    let complete = |shell: &Session, line: &str| {
+        let (_, candidates) = shell.complete(line, line.len(), &ctx).unwrap();
+        candidates
+            .into_iter()
+            .map(|c| c.replacement)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(complete(&shell, "ra"), ["range"]);
+    assert_eq!(complete(&shell, "use t"), ["tags"]);
+    assert_eq!(complete(&shell, "get "), ["alice", "'bob smith'"]);
+    assert_eq!(complete(&shell, "rm b"), ["'bob smith'"]);
+
+    shell.exec("use tags").unwrap();
+    shell.exec("put 1 a").unwrap();
+    shell.exec("put 1 b").unwrap();
+    shell.exec("rm 1 a").unwrap();
+    assert!(shell.exec("rm 1 a").is_err());
+    shell.exec("get 1").unwrap();
+    assert_eq!(buf.take(), "b\n");
+    assert_eq!(complete(&shell, "get "), ["1"]);
+
+    // Keys that aren't strings are scanned up to a limit
+    shell.exec("use counts").unwrap();
+    assert_eq!(complete(&shell, "get 9999"), ["9999"]);
+    assert!(complete(&shell, "get 10000").is_empty());
+
+    assert!(!shell.exec("exit").unwrap());
+}
@@ -236,4 +236,44 @@ fn test_structs() {
             timeout_ms: None,
         };
     }
+
+    // Tuple, newtype, unit and empty structs
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Pair(u32, String);
+
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Wrapper(u64);
+
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Name(String);
+
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Marker;
+
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Empty {}
+
+    #[derive(Debug, Serialize, Deserialize, Value)]
+    struct Tagged {
+        id: Wrapper,
+        name: Name,
+        pair: Pair,
+        marker: Marker,
+    }
+
+    test! {
+        let pair: Pair = Pair(7, "seven".into());
+        let wrapper: Wrapper = Wrapper(u64::MAX);
+        let name: Name = Name("".into());
+        let marker: Marker = Marker;
+        let empty: Empty = Empty {};
+        let tagged: Tagged = Tagged {
+            id: Wrapper(1),
+            name: Name("one".into()),
+            pair: Pair(0, "".into()),
+            marker: Marker,
+        };
+        let wrappers: Vec<Wrapper> = vec![Wrapper(1), Wrapper(2)];
+        let names: (Name, Name) = (Name("a".into()), Name("b".into()));
+    }
 }
@@ -0,0 +1,111 @@
+use ratatui::{
+    Terminal,
+    backend::TestBackend,
+    crossterm::event::{KeyCode, KeyEvent},
+};
+use redb::{Database, MultimapTableDefinition, TableDefinition, backends::InMemoryBackend};
+use serde_json::json;
+
+use crate::{DB, cli::CliArgs, tui::App};
+
+fn press(app: &mut App, keys: &[KeyCode]) {
+    for &key in keys {
+        app.handle_key(KeyEvent::from(key));
+    }
+}
+
+fn screen(app: &mut App) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(120, 12)).unwrap();
+    terminal.draw(|frame| app.draw(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let lines = buffer.content().chunks(buffer.area.width as usize);
+    let lines = lines.map(|l| l.iter().map(|c| c.symbol()).collect::<String>());
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn test_tui() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let w = db.begin_write().unwrap();
+    let mut users = w
+        .open_table(TableDefinition::<&str, u64>::new("users"))
+        .unwrap();
+    for (i, name) in ["alice", "bob", "carol"].into_iter().enumerate() {
+        users.insert(name, i as u64 + 1).unwrap();
+    }
+    drop(users);
+    let mut tags = w
+        .open_multimap_table(MultimapTableDefinition::<u64, &str>::new("tags"))
+        .unwrap();
+    tags.insert(1, "a").unwrap();
+    tags.insert(1, "b").unwrap();
+    drop(tags);
+    w.commit().unwrap();
+
+    let mut app = App::new(CliArgs::default(), DB::RW(db)).unwrap();
+    let keys = |app: &App| {
+        app.entries
+            .rows
+            .iter()
+            .map(|r| r.key.clone())
+            .collect::<Vec<_>>()
+    };
+    let screen_ = screen(&mut app);
+    assert!(screen_.contains("tags (multimap)"), "{screen_}");
+    assert!(screen_.contains("u64 -> &str"), "{screen_}");
+    assert!(screen_.contains("1: a"), "{screen_}");
+
+    // Edit the value of bob
+    press(
+        &mut app,
+        &[KeyCode::Char('j'), KeyCode::Tab, KeyCode::Char('j')],
+    );
+    press(
+        &mut app,
+        &[KeyCode::Char('e'), KeyCode::Backspace, KeyCode::Char('x')],
+    );
+    press(&mut app, &[KeyCode::Enter]);
+    let s = screen(&mut app);
+    assert!(s.contains("Invalid value"), "{s}");
+    press(
+        &mut app,
+        &[KeyCode::Backspace, KeyCode::Char('5'), KeyCode::Enter],
+    );
+    assert!(screen(&mut app).contains("Replace the value of bob? [y/N]"));
+    press(&mut app, &[KeyCode::Char('y')]);
+    assert_eq!(app.entries.rows[1].value, json!(5));
+    assert!(screen(&mut app).contains("bob: 5"));
+
+    // Searching moves to the first key at or after the query, Esc goes back
+    press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('c')]);
+    assert_eq!(keys(&app), [json!("carol")]);
+    press(&mut app, &[KeyCode::Esc]);
+    assert_eq!(keys(&app).len(), 3);
+
+    press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('n')]);
+    assert!(screen(&mut app).contains("Cancelled"));
+    press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]);
+    assert_eq!(keys(&app), [json!("alice"), json!("carol")]);
+
+    // A single value of a multimap key is deleted
+    press(&mut app, &[KeyCode::Tab, KeyCode::Char('k'), KeyCode::Tab]);
+    press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]);
+    press(&mut app, &[KeyCode::Char('r')]);
+    assert_eq!(app.entries.rows.len(), 1);
+    assert_eq!(app.entries.rows[0].value, json!("b"));
+
+    press(&mut app, &[KeyCode::Tab, KeyCode::Char('j')]);
+    assert_eq!(keys(&app), [json!("alice"), json!("carol")]);
+    assert_eq!(app.entries.rows[1].value, json!(3));
+
+    // Keys out of the range of the key type are reported instead of searched
+    press(&mut app, &[KeyCode::Char('k')]);
+    for c in "/-1".chars() {
+        press(&mut app, &[KeyCode::Char(c)]);
+    }
+    let s = screen(&mut app);
+    assert!(s.contains("Invalid key: expected u64, found -1"), "{s}");
+    assert_eq!(app.entries.rows[0].value, json!("b"));
+}
@@ -0,0 +1,42 @@
+use redb::{Legacy, TypeName, Value};
+
+use crate::typename::{Class, classify, type_name};
+
+#[test]
+fn test_type_names() {
+    macro_rules! test {
+        ($($ty:ty => $class:ident,)*) => {
+            $(
+                let ty = <$ty>::type_name();
+                assert_eq!(classify(&ty), Some(Class::$class), "{ty:?}");
+                assert_eq!(type_name(ty.name(), Class::$class).unwrap(), ty);
+            )*
+        };
+    }
+
+    test!(
+        u64 => Internal,
+        &str => Internal,
+        () => Internal,
+        &[u8] => Internal,
+        Option<String> => Internal,
+        Vec<Option<i8>> => Internal,
+        [u8; 32] => Internal,
+        &[u8; 16] => Internal,
+        [(u8, String); 3] => Internal,
+        (u64,) => Internal,
+        (u64, i32) => Internal,
+        (u64, String) => Internal2,
+        (Option<(u8, String)>, Vec<u8>, bool) => Internal2,
+        Legacy<(u64, String)> => Internal,
+        Option<(u8, &str)> => Internal,
+    );
+
+    let ty = TypeName::new("Log {time: u64, line: String}");
+    assert_eq!(classify(&ty), Some(Class::User));
+    let ty = TypeName::new("u64");
+    assert_eq!(classify(&ty), Some(Class::User));
+
+    assert!(type_name("Log {time: u64}", Class::Internal).is_err());
+    assert!(type_name("[u8;33333]", Class::Internal).is_err());
+}
@@ -1,31 +1,49 @@
-use eyre::Result;
-use pest::iterators::Pair;
+use eyre::{OptionExt, Result, WrapErr};
 use redb::{TypeName, Value};
 use std::{
     cell::{Cell, RefCell},
     cmp::Ordering,
+    thread::LocalKey,
 };
 
-use crate::parser::{Rule, encode, ordering, parse_from_tree};
+use crate::{
+    WARNING,
+    parser::{self, TypeDesc, encode, parse_from_tree, raw_value},
+};
 
 std::thread_local! {
     pub static K_NAME: RefCell<TypeName> = RefCell::new(String::type_name());
     pub static K_WIDTH: Cell<Option<usize>> = const { Cell::new(None) };
-    pub static K_TREE: RefCell<Option<Pair<'static, Rule>>> = RefCell::new(None);
+    pub static K_TREE: RefCell<Option<TypeDesc>> = const { RefCell::new(None) };
 
     pub static V_NAME: RefCell<TypeName> = RefCell::new(String::type_name());
     pub static V_WIDTH: Cell<Option<usize>> = const { Cell::new(None) };
-    pub static V_TREE: RefCell<Option<Pair<'static, Rule>>> = RefCell::new(None);
+    pub static V_TREE: RefCell<Option<TypeDesc>> = const { RefCell::new(None) };
+}
+
+/// Set the type names and widths of a schema given by the user, e.g. `u64 -> String`
+pub fn set_schema(schema: &str) -> Result<()> {
+    let (k_ty, v_ty) = schema
+        .split_once(" -> ")
+        .ok_or_eyre("Use -> to separate key and value types")?;
+
+    let k_tree = parser::parse_tree(k_ty)?;
+    let v_tree = parser::parse_tree(v_ty)?;
+
+    K_NAME.set(TypeName::new(k_ty));
+    K_WIDTH.set(parser::parse_size(&k_tree));
+
+    V_NAME.set(TypeName::new(v_ty));
+    V_WIDTH.set(parser::parse_size(&v_tree));
+
+    Ok(())
 }
 
 pub fn val_to_string(ty: &'static str, val: serde_json::Value) -> String {
-    if matches!(ty, "&str" | "String") {
-        match val {
-            serde_json::Value::String(res) => res,
-            _ => panic!("Invalid value type"),
-        }
-    } else {
-        serde_json::to_string(&val).unwrap()
+    match val {
+        serde_json::Value::String(res) if matches!(ty, "&str" | "String") => res,
+        // Entries that failed to decode are shown as raw bytes even for strings
+        val => serde_json::to_string(&val).unwrap(),
     }
 }
 
@@ -37,6 +55,35 @@ pub fn string_to_val(ty: &'static str, val: &str) -> Result<serde_json::Value> {
     }
 }
 
+/// Keys and values given by the user are checked up front, since [`redb::Value::as_bytes`] can't
+/// fail
+fn check(
+    tree: &'static LocalKey<RefCell<Option<TypeDesc>>>,
+    what: &str,
+    val: serde_json::Value,
+) -> Result<serde_json::Value> {
+    tree.with_borrow(|t| encode(t.as_ref().unwrap(), &val, &mut Vec::new()))
+        .wrap_err_with(|| format!("Invalid {what}"))?;
+    Ok(val)
+}
+
+pub fn check_key(val: serde_json::Value) -> Result<serde_json::Value> {
+    check(&K_TREE, "key", val)
+}
+
+pub fn check_value(val: serde_json::Value) -> Result<serde_json::Value> {
+    check(&V_TREE, "value", val)
+}
+
+/// Decode an entry, falling back to its raw bytes, so one bad entry doesn't stop the output
+fn decode(tree: &'static LocalKey<RefCell<Option<TypeDesc>>>, data: &[u8]) -> serde_json::Value {
+    tree.with_borrow(|t| parse_from_tree(t.as_ref().unwrap(), data))
+        .unwrap_or_else(|err| {
+            eprintln!("{WARNING} Error decoding entry, showing raw bytes: {err}");
+            raw_value(data)
+        })
+}
+
 #[derive(Debug)]
 pub struct K;
 
@@ -45,19 +92,13 @@ pub struct V;
 
 impl redb::Key for K {
     fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
-        let ty = K_TREE.with_borrow(|t| t.clone().unwrap());
-        let l = parse_from_tree(ty.clone(), data1).unwrap();
-        let r = parse_from_tree(ty.clone(), data2).unwrap();
-        ordering(&l, &r).unwrap()
+        K_TREE.with_borrow(|t| t.as_ref().unwrap().compare(data1, data2))
     }
 }
 
 impl redb::Key for V {
     fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
-        let ty = V_TREE.with_borrow(|t| t.clone().unwrap());
-        let l = parse_from_tree(ty.clone(), data1).unwrap();
-        let r = parse_from_tree(ty.clone(), data2).unwrap();
-        ordering(&l, &r).unwrap()
+        V_TREE.with_borrow(|t| t.as_ref().unwrap().compare(data1, data2))
     }
 }
 
@@ -76,9 +117,10 @@ impl redb::Value for K {
     where
         Self: 'b,
     {
-        let ty = K_TREE.with_borrow(|t| t.clone().unwrap());
         let mut buf = Vec::new();
-        encode(ty, value, &mut buf).unwrap();
+        K_TREE
+            .with_borrow(|t| encode(t.as_ref().unwrap(), value, &mut buf))
+            .expect("Keys are checked before use");
         buf
     }
 
@@ -86,8 +128,7 @@ impl redb::Value for K {
     where
         Self: 'a,
     {
-        let ty = K_TREE.with_borrow(|t| t.clone().unwrap());
-        parse_from_tree(ty, data).unwrap()
+        decode(&K_TREE, data)
     }
 
     fn fixed_width() -> Option<usize> {
@@ -114,9 +155,10 @@ impl redb::Value for V {
     where
         Self: 'b,
     {
-        let ty = V_TREE.with_borrow(|t| t.clone().unwrap());
         let mut buf = Vec::new();
-        encode(ty, value, &mut buf).unwrap();
+        V_TREE
+            .with_borrow(|t| encode(t.as_ref().unwrap(), value, &mut buf))
+            .expect("Values are checked before use");
         buf
     }
 
@@ -124,8 +166,7 @@ impl redb::Value for V {
     where
         Self: 'a,
     {
-        let ty = V_TREE.with_borrow(|t| t.clone().unwrap());
-        parse_from_tree(ty, data).unwrap()
+        decode(&V_TREE, data)
     }
 
     fn fixed_width() -> Option<usize> {
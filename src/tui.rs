@@ -0,0 +1,651 @@
+//! Full-screen browser of tables and entries, reading a page of entries at a time
+
+use eyre::{Result, WrapErr, bail};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+};
+use redb::{MultimapTableDefinition, MultimapTableHandle, TableDefinition, TableHandle};
+use serde_json::Value;
+use std::ops::Bound;
+
+use crate::{
+    DB, KVType, Schema,
+    cli::CliArgs,
+    parser,
+    transcode::{K, K_TREE, V, V_TREE, check_key, check_value, string_to_val, val_to_string},
+};
+
+/// Keys read from the table at once
+const PAGE: usize = 200;
+
+pub fn run(args: &CliArgs, db: DB) -> Result<()> {
+    let mut app = App::new(args.clone(), db)?;
+    ratatui::run(|terminal| app.run(terminal))
+}
+
+struct TableInfo {
+    name: String,
+    /// Tables that can't be opened are listed with the error
+    schema: Result<Schema, String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Row {
+    pub key: Value,
+    pub value: Value,
+}
+
+/// Entries of the selected table read so far, one row per value of multimap keys
+#[derive(Clone, Default)]
+pub(crate) struct Entries {
+    pub rows: Vec<Row>,
+    state: ListState,
+    /// Keys read so far
+    keys: usize,
+    /// Whether the table may have more keys
+    more: bool,
+    /// First key to show, moved by searches
+    start: Option<Value>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Focus {
+    Tables,
+    Entries,
+}
+
+enum Mode {
+    Normal,
+    /// Incremental key search, the entries before it are restored on Esc
+    Search {
+        query: Input,
+        saved: Entries,
+    },
+    /// Editing the value of the selected entry
+    Edit(Input),
+    /// Waiting for y to change the selected entry
+    Confirm(Change),
+}
+
+enum Change {
+    Replace(Value),
+    Delete,
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+#[derive(Default)]
+struct Input {
+    text: String,
+    /// Position in chars
+    cursor: usize,
+}
+
+impl Input {
+    fn new(text: String) -> Self {
+        let cursor = text.chars().count();
+        Self { text, cursor }
+    }
+
+    fn offset(&self) -> usize {
+        let mut chars = self.text.char_indices().skip(self.cursor);
+        chars.next().map_or(self.text.len(), |(i, _)| i)
+    }
+
+    /// Returns whether the text changed
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char(c) => {
+                let offset = self.offset();
+                self.text.insert(offset, c);
+                self.cursor += 1;
+                return true;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let offset = self.offset();
+                self.text.remove(offset);
+                return true;
+            }
+            KeyCode::Delete if self.cursor < self.text.chars().count() => {
+                let offset = self.offset();
+                self.text.remove(offset);
+                return true;
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.chars().count(),
+            _ => {}
+        }
+        false
+    }
+
+    /// The text with the cursor shown as a reversed char
+    fn spans(&self) -> Vec<Span<'_>> {
+        let (before, after) = self.text.split_at(self.offset());
+        let mut after = after.chars();
+        let cursor = after.next().map_or(" ".into(), String::from);
+        vec![
+            Span::raw(before),
+            Span::raw(cursor).reversed(),
+            Span::raw(after.as_str()),
+        ]
+    }
+}
+
+pub(crate) struct App {
+    args: CliArgs,
+    db: DB,
+    tables: Vec<TableInfo>,
+    table_state: ListState,
+    pub entries: Entries,
+    focus: Focus,
+    mode: Mode,
+    status: Option<Status>,
+    reloaded: bool,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(args: CliArgs, db: DB) -> Result<Self> {
+        let mut app = Self {
+            args,
+            db,
+            tables: Vec::new(),
+            table_state: ListState::default(),
+            entries: Entries::default(),
+            focus: Focus::Tables,
+            mode: Mode::Normal,
+            status: None,
+            reloaded: false,
+            quit: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.handle_key(key);
+            }
+            // Warnings about types are written over the screen
+            if std::mem::take(&mut self.reloaded) {
+                terminal.clear()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the table list again, keeping the selected table
+    fn reload(&mut self) -> Result<()> {
+        let selected = self.table().map(|t| t.name.clone());
+
+        let r = self.db.begin_read()?;
+        let mut names: Vec<_> = r.list_tables()?.map(|t| t.name().to_string()).collect();
+        names.extend(r.list_multimap_tables()?.map(|t| t.name().to_string()));
+        names.sort();
+        drop(r);
+
+        let args = CliArgs {
+            create: false,
+            multimap: false,
+            ..self.args.clone()
+        };
+        self.tables = names
+            .into_iter()
+            .map(|name| TableInfo {
+                schema: Schema::resolve(&args, &self.db, &name).map_err(|err| format!("{err:#}")),
+                name,
+            })
+            .collect();
+
+        let index = selected
+            .and_then(|s| self.tables.iter().position(|t| t.name == s))
+            .or((!self.tables.is_empty()).then_some(0));
+        self.table_state.select(index);
+        self.reloaded = true;
+        self.open_table()
+    }
+
+    fn table(&self) -> Option<&TableInfo> {
+        self.table_state.selected().and_then(|i| self.tables.get(i))
+    }
+
+    fn schema(&self) -> Result<&Schema> {
+        match self.table().map(|t| &t.schema) {
+            Some(Ok(schema)) => Ok(schema),
+            Some(Err(err)) => bail!("{err}"),
+            None => bail!("No table selected"),
+        }
+    }
+
+    /// Show the entries of the selected table from the start
+    fn open_table(&mut self) -> Result<()> {
+        self.entries = Entries {
+            more: true,
+            ..Default::default()
+        };
+        if self.table().is_some_and(|t| t.schema.is_ok()) {
+            self.load_more()?;
+        }
+        Ok(())
+    }
+
+    /// Read the next page of entries
+    fn load_more(&mut self) -> Result<()> {
+        let schema = self.schema()?;
+        let (ty, can_order) = (schema.enter(), schema.can_order());
+        let name = self.table().unwrap().name.clone();
+        let entries = &mut self.entries;
+        if !entries.more {
+            return Ok(());
+        }
+
+        // Keys that can't be ordered can't be looked up, so they're skipped instead
+        let (from, skip) = match entries.rows.last() {
+            Some(row) if can_order => (Bound::Excluded(row.key.clone()), 0),
+            Some(_) => (Bound::Unbounded, entries.keys),
+            None => (
+                entries
+                    .start
+                    .clone()
+                    .map_or(Bound::Unbounded, Bound::Included),
+                0,
+            ),
+        };
+        let range = (from, Bound::Unbounded);
+
+        let mut keys = 0;
+        let r = self.db.begin_read()?;
+        if ty.is_multi {
+            let table = r.open_multimap_table(MultimapTableDefinition::<K, V>::new(&name))?;
+            for entry in table.range::<Value>(range)?.skip(skip).take(PAGE) {
+                let (k, values) = entry?;
+                let key = k.value();
+                for v in values {
+                    let value = v?.value();
+                    entries.rows.push(Row {
+                        key: key.clone(),
+                        value,
+                    });
+                }
+                keys += 1;
+            }
+        } else {
+            let table = r.open_table(TableDefinition::<K, V>::new(&name))?;
+            for entry in table.range::<Value>(range)?.skip(skip).take(PAGE) {
+                let (k, v) = entry?;
+                entries.rows.push(Row {
+                    key: k.value(),
+                    value: v.value(),
+                });
+                keys += 1;
+            }
+        }
+
+        entries.keys += keys;
+        entries.more = keys == PAGE;
+        if entries.state.selected().is_none() && !entries.rows.is_empty() {
+            entries.state.select(Some(0));
+        }
+        Ok(())
+    }
+
+    /// Move the selection of the focused pane, reading entries as needed
+    fn select(&mut self, to: impl FnOnce(usize) -> usize) -> Result<()> {
+        match self.focus {
+            Focus::Tables => {
+                if self.tables.is_empty() {
+                    return Ok(());
+                }
+                let index = to(self.table_state.selected().unwrap_or(0));
+                let index = index.min(self.tables.len() - 1);
+                if Some(index) != self.table_state.selected() {
+                    self.table_state.select(Some(index));
+                    self.open_table()?;
+                }
+            }
+            Focus::Entries => {
+                let index = to(self.entries.state.selected().unwrap_or(0));
+                while index >= self.entries.rows.len() && self.entries.more {
+                    self.load_more()?;
+                }
+                let last = self.entries.rows.len().saturating_sub(1);
+                if !self.entries.rows.is_empty() {
+                    self.entries.state.select(Some(index.min(last)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn row(&self) -> Option<&Row> {
+        let index = self.entries.state.selected()?;
+        self.entries.rows.get(index)
+    }
+
+    fn ty(&self) -> Option<KVType<&'static String>> {
+        self.schema().ok().map(Schema::enter)
+    }
+
+    /// Jump to the first key at or after the query, which may be a prefix of a tuple or struct
+    fn search(&mut self, query: &str) -> Result<()> {
+        let schema = self.schema()?;
+        let ty = schema.enter();
+        let start = match query {
+            "" => None,
+            query => {
+                let key = string_to_val(ty.k_ty, query)?;
+                let key = K_TREE.with_borrow(|t| parser::pad_prefix(t.as_ref().unwrap(), key))?;
+                Some(check_key(key)?)
+            }
+        };
+        self.entries = Entries {
+            more: true,
+            start,
+            ..Default::default()
+        };
+        self.load_more()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        self.status = None;
+        if let Err(err) = self.try_handle_key(key) {
+            self.status = Some(Status::Error(format!("{err:#}")));
+        }
+    }
+
+    fn try_handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        let ctrl_c =
+            key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+        if ctrl_c {
+            self.quit = true;
+            return Ok(());
+        }
+
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal(key),
+            Mode::Search { mut query, saved } => match key.code {
+                KeyCode::Enter => Ok(()),
+                KeyCode::Esc => {
+                    self.entries = saved;
+                    Ok(())
+                }
+                _ => {
+                    let changed = query.handle(key);
+                    let res = if changed {
+                        self.search(&query.text)
+                    } else {
+                        Ok(())
+                    };
+                    self.mode = Mode::Search { query, saved };
+                    // Partly typed keys that don't parse yet keep the entries
+                    if let Err(err) = res {
+                        self.status = Some(Status::Info(format!("{err:#}")));
+                    }
+                    Ok(())
+                }
+            },
+            Mode::Edit(mut input) => match key.code {
+                KeyCode::Esc => Ok(()),
+                KeyCode::Enter => {
+                    let ty = self.ty().unwrap();
+                    let value = string_to_val(ty.v_ty, &input.text)
+                        .wrap_err("Invalid value")
+                        .and_then(check_value);
+                    match value {
+                        Ok(value) => self.mode = Mode::Confirm(Change::Replace(value)),
+                        Err(err) => {
+                            self.mode = Mode::Edit(input);
+                            return Err(err);
+                        }
+                    }
+                    Ok(())
+                }
+                _ => {
+                    input.handle(key);
+                    self.mode = Mode::Edit(input);
+                    Ok(())
+                }
+            },
+            Mode::Confirm(change) => match key.code {
+                KeyCode::Char('y' | 'Y') => self.apply(change),
+                _ => {
+                    self.status = Some(Status::Info("Cancelled".into()));
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn handle_normal(&mut self, key: KeyEvent) -> Result<()> {
+        let page = PAGE / 10;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Tables => Focus::Entries,
+                    Focus::Entries => Focus::Tables,
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.focus = Focus::Tables,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.focus = Focus::Entries,
+            KeyCode::Down | KeyCode::Char('j') => self.select(|i| i + 1)?,
+            KeyCode::Up | KeyCode::Char('k') => self.select(|i| i.saturating_sub(1))?,
+            KeyCode::PageDown => self.select(|i| i + page)?,
+            KeyCode::PageUp => self.select(|i| i.saturating_sub(page))?,
+            KeyCode::Home | KeyCode::Char('g') => self.select(|_| 0)?,
+            KeyCode::End | KeyCode::Char('G') => {
+                // Only the entries read so far, the table may be large
+                let last = match self.focus {
+                    Focus::Tables => self.tables.len(),
+                    Focus::Entries => self.entries.rows.len(),
+                };
+                self.select(|_| last.saturating_sub(1))?
+            }
+            KeyCode::Char('r') => {
+                self.reload()?;
+                self.status = Some(Status::Info("Reloaded".into()));
+            }
+            KeyCode::Char('/') => {
+                if !self.schema()?.can_order() {
+                    bail!("Keys of {:?} can't be ordered", self.table().unwrap().name);
+                }
+                self.focus = Focus::Entries;
+                self.mode = Mode::Search {
+                    query: Input::default(),
+                    saved: self.entries.clone(),
+                };
+            }
+            KeyCode::Char('e') => {
+                let (Some(ty), Some(row)) = (self.ty(), self.row()) else {
+                    return Ok(());
+                };
+                let text = val_to_string(ty.v_ty, row.value.clone());
+                self.focus = Focus::Entries;
+                self.mode = Mode::Edit(Input::new(text));
+            }
+            KeyCode::Char('d') if self.row().is_some() => {
+                self.focus = Focus::Entries;
+                self.mode = Mode::Confirm(Change::Delete);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Write a confirmed change of the selected entry
+    fn apply(&mut self, change: Change) -> Result<()> {
+        let schema = self.schema()?;
+        let name = self.table().unwrap().name.clone();
+        let ty = schema.enter();
+        if !schema.can_order() {
+            bail!("Keys of {name:?} can't be ordered");
+        }
+        if ty.is_multi && !V_TREE.with_borrow(|t| parser::can_order(t.as_ref().unwrap())) {
+            bail!("Values of {name:?} can't be ordered");
+        }
+        let index = self.entries.state.selected().unwrap();
+        let Row { key, value } = self.entries.rows[index].clone();
+        let key_str = val_to_string(ty.k_ty, key.clone());
+
+        let w = self.db.begin_write()?;
+        if ty.is_multi {
+            let mut table = w.open_multimap_table(MultimapTableDefinition::<K, V>::new(&name))?;
+            table.remove(&key, &value)?;
+            if let Change::Replace(new) = &change {
+                table.insert(&key, new)?;
+            }
+        } else {
+            let mut table = w.open_table(TableDefinition::<K, V>::new(&name))?;
+            match &change {
+                Change::Replace(new) => table.insert(&key, new)?,
+                Change::Delete => table.remove(&key)?,
+            };
+        }
+        w.commit()?;
+
+        let status = match change {
+            Change::Replace(new) => {
+                self.entries.rows[index].value = new;
+                format!("Replaced the value of {key_str}")
+            }
+            Change::Delete => {
+                self.entries.rows.remove(index);
+                if index >= self.entries.rows.len() {
+                    self.entries.state.select(index.checked_sub(1));
+                }
+                format!("Deleted {key_str}")
+            }
+        };
+        self.status = Some(Status::Info(status));
+        Ok(())
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [tables, entries, detail] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(40),
+            Constraint::Percentage(35),
+        ])
+        .areas(main);
+
+        let block = |title: String, focused: bool| {
+            let block = Block::bordered().title(title);
+            match focused {
+                true => block.border_style(Style::new().fg(Color::Cyan)),
+                false => block,
+            }
+        };
+        let highlight = Style::new().reversed();
+
+        let items = self.tables.iter().map(|t| {
+            let types = match &t.schema {
+                Ok(schema) => {
+                    let [k, v] = schema.names();
+                    Span::raw(format!("  {k} -> {v}")).dim()
+                }
+                Err(err) => Span::raw(format!("  {err}")).red(),
+            };
+            let multi = match &t.schema {
+                Ok(schema) if schema.enter().is_multi => " (multimap)",
+                _ => "",
+            };
+            let name = Line::from(vec![t.name.as_str().bold(), multi.dim()]);
+            ListItem::new(Text::from(vec![name, types.into()]))
+        });
+        let list = List::new(items)
+            .block(block("Tables".into(), self.focus == Focus::Tables))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, tables, &mut self.table_state);
+
+        let ty = self.ty();
+        let title = match self.table() {
+            Some(t) => {
+                let more = if self.entries.more { "+" } else { "" };
+                format!("{} ({}{more})", t.name, self.entries.rows.len())
+            }
+            None => "Entries".into(),
+        };
+        let items = self.entries.rows.iter().map(|row| {
+            let ty = ty.unwrap();
+            let key = val_to_string(ty.k_ty, row.key.clone());
+            let value = val_to_string(ty.v_ty, row.value.clone());
+            ListItem::new(Line::from(vec![
+                Span::raw(key).bold(),
+                Span::raw(": "),
+                Span::raw(value),
+            ]))
+        });
+        let list = List::new(items)
+            .block(block(title, self.focus == Focus::Entries))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, entries, &mut self.entries.state);
+
+        let text = match (&self.mode, self.row(), ty) {
+            (Mode::Edit(input), _, _) => Text::from(Line::from(input.spans())),
+            (_, Some(row), Some(ty)) => {
+                let key = val_to_string(ty.k_ty, row.key.clone());
+                let value = serde_json::to_string_pretty(&row.value).unwrap_or_default();
+                let mut text = Text::from(Line::from(vec!["key: ".dim(), key.bold()]));
+                text.extend(Text::from(value));
+                text
+            }
+            _ => Text::default(),
+        };
+        let title = match self.mode {
+            Mode::Edit(_) => "Edit value",
+            _ => "Value",
+        };
+        let paragraph = Paragraph::new(text)
+            .block(block(title.into(), matches!(self.mode, Mode::Edit(_))))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(paragraph, detail);
+
+        let line = match (&self.mode, &self.status) {
+            (Mode::Search { query, .. }, status) => {
+                let mut spans = vec![Span::raw("/")];
+                spans.extend(query.spans());
+                if let Some(Status::Info(msg) | Status::Error(msg)) = status {
+                    spans.push(Span::raw(format!("  {msg}")).dim());
+                }
+                Line::from(spans)
+            }
+            (_, Some(Status::Error(msg))) => Line::from(msg.as_str().red()),
+            (Mode::Confirm(change), _) => {
+                let (row, ty) = (self.row().unwrap(), ty.unwrap());
+                let key = val_to_string(ty.k_ty, row.key.clone());
+                let question = match change {
+                    Change::Replace(_) => format!("Replace the value of {key}? [y/N]"),
+                    Change::Delete if ty.is_multi => {
+                        let value = val_to_string(ty.v_ty, row.value.clone());
+                        format!("Delete the value {value} of {key}? [y/N]")
+                    }
+                    Change::Delete => format!("Delete {key}? [y/N]"),
+                };
+                Line::from(question.yellow())
+            }
+            (Mode::Edit(_), _) => Line::from("Enter save  Esc cancel".dim()),
+            (_, Some(Status::Info(msg))) => Line::from(msg.as_str().green()),
+            (Mode::Normal, None) => {
+                Line::from("q quit  tab switch pane  / search  e edit  d delete  r reload".dim())
+            }
+        };
+        frame.render_widget(line, status);
+    }
+}
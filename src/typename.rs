@@ -0,0 +1,180 @@
+//! Exact [`TypeName`]s of tables, so restored tables can be opened by the original application.
+//!
+//! Names of built-in types are marked internal, which can't be done through redb's API, but
+//! containers only use the names of their parameters. So such names are rebuilt from the
+//! outermost container, with placeholder parameters whose names are set at runtime.
+
+use eyre::{Result, bail};
+use redb::{Legacy, TypeName, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// How redb marks a type name, user types are the ones named with [`TypeName::new`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    User,
+    Internal,
+    /// Variable width tuples since redb 3.0
+    Internal2,
+}
+
+std::thread_local! {
+    static PARAMS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Type parameter named by the `I`th element of [`PARAMS`]
+#[derive(Debug)]
+struct Param<const I: usize>;
+
+impl<const I: usize> Value for Param<I> {
+    type SelfType<'a> = ();
+
+    type AsBytes<'a> = [u8; 0];
+
+    // Makes tuples of parameters variable width, see [`Class::Internal2`]
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(_: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+    }
+
+    fn as_bytes<'a, 'b: 'a>(_: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b,
+    {
+        []
+    }
+
+    fn type_name() -> TypeName {
+        PARAMS.with_borrow(|p| TypeName::new(&p[I]))
+    }
+}
+
+/// Classification of a type name read from the database
+pub fn classify(ty: &TypeName) -> Option<Class> {
+    [Class::User, Class::Internal, Class::Internal2]
+        .into_iter()
+        .find(|class| type_name(ty.name(), *class).is_ok_and(|t| &t == ty))
+}
+
+pub fn type_name(name: &str, class: Class) -> Result<TypeName> {
+    let ty = match class {
+        Class::User => TypeName::new(name),
+        Class::Internal | Class::Internal2 => internal(name, class)?,
+    };
+    if ty.name() != name {
+        bail!("Can't reproduce type name {name:?}");
+    }
+    Ok(ty)
+}
+
+fn internal(name: &str, class: Class) -> Result<TypeName> {
+    macro_rules! primitives {
+        ($($ty:ty);*) => {
+            $(
+                if name == <$ty>::type_name().name() {
+                    return Ok(<$ty>::type_name());
+                }
+            )*
+        };
+    }
+    primitives!(
+        u8; u16; u32; u64; u128; i8; i16; i32; i64; i128; f32; f64; bool; char; &str; String;
+        &[u8]; ()
+    );
+
+    let err = || eyre::eyre!("Unknown internal type {name:?}");
+
+    let (params, ty) = if let Some(inner) = strip(name, "Option<", ">") {
+        (vec![inner], <Option<Param<0>>>::type_name as _)
+    } else if let Some(inner) = strip(name, "Vec<", ">") {
+        (vec![inner], <Vec<Param<0>>>::type_name as _)
+    } else if let Some(inner) = strip(name, "[", "]") {
+        let (inner, n) = inner.rsplit_once(';').ok_or_else(err)?;
+        (vec![inner], array(n.parse()?).ok_or_else(err)?)
+    } else if let Some(inner) = strip(name, "(", ",)") {
+        (vec![inner], <(Param<0>,)>::type_name as _)
+    } else if let Some(inner) = strip(name, "(", ")") {
+        let params = split(inner);
+        let ty = tuple(params.len(), class == Class::Internal2).ok_or_else(err)?;
+        (params, ty)
+    } else {
+        return Err(err());
+    };
+
+    PARAMS.set(params.into_iter().map(str::to_string).collect());
+    Ok(ty())
+}
+
+fn strip<'a>(name: &'a str, prefix: &str, suffix: &str) -> Option<&'a str> {
+    name.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// Split tuple elements on commas outside of brackets
+fn split(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(&s[start..]);
+    res
+}
+
+/// Tuples of known length, redb implements them for up to 12 elements
+fn tuple(n: usize, internal2: bool) -> Option<fn() -> TypeName> {
+    macro_rules! tuples {
+        ($($n:literal => ($($i:literal),*),)*) => {
+            match n {
+                $(
+                    $n if internal2 => Some(<($(Param<$i>,)*)>::type_name),
+                    $n => Some(<Legacy<($(Param<$i>,)*)>>::type_name),
+                )*
+                _ => None,
+            }
+        };
+    }
+    tuples!(
+        2 => (0, 1),
+        3 => (0, 1, 2),
+        4 => (0, 1, 2, 3),
+        5 => (0, 1, 2, 3, 4),
+        6 => (0, 1, 2, 3, 4, 5),
+        7 => (0, 1, 2, 3, 4, 5, 6),
+        8 => (0, 1, 2, 3, 4, 5, 6, 7),
+        9 => (0, 1, 2, 3, 4, 5, 6, 7, 8),
+        10 => (0, 1, 2, 3, 4, 5, 6, 7, 8, 9),
+        11 => (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10),
+        12 => (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11),
+    )
+}
+
+/// Array lengths are part of the type, so only common ones are covered
+fn array(n: usize) -> Option<fn() -> TypeName> {
+    macro_rules! arrays {
+        ($($n:literal)*) => {
+            match n {
+                $( $n => Some(<[Param<0>; $n]>::type_name), )*
+                _ => None,
+            }
+        };
+    }
+    arrays!(
+        0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+        33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62
+        63 64 96 100 128 160 192 200 256 384 512 1000 1024 2048 4096
+    )
+}
@@ -16,6 +16,25 @@ pub fn dump_hex(buf: &[u8]) {
     }
 }
 
+pub fn to_hex(buf: &[u8]) -> String {
+    let mut res = String::with_capacity(buf.len() * 2);
+    for b in buf {
+        res.push_str(&format!("{b:02x}"));
+    }
+    res
+}
+
+pub fn from_hex(s: &str) -> eyre::Result<Vec<u8>> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        eyre::bail!("Invalid hex string {s:?}");
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&s[i..i + 2], 16)?))
+        .collect()
+}
+
 #[allow(unused)]
 #[track_caller]
 pub fn dump_assert_eq(left: &[u8], right: &[u8]) {
@@ -54,3 +73,26 @@ impl<T> OrElseRes for Option<T> {
         }
     }
 }
+
+/// Output of a printer in tests, read back once it's written
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Buf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Buf {
+    /// Output written so far, leaving the buffer empty
+    pub fn take(&self) -> String {
+        String::from_utf8(self.0.take()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for Buf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}